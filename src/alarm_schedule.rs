@@ -1,8 +1,17 @@
-use std::time::Duration;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::Duration,
+};
 
 use sqlx::SqlitePool;
 use tokio::{
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        broadcast,
+        mpsc::{Receiver, Sender},
+    },
     task::JoinHandle,
 };
 
@@ -10,25 +19,38 @@ use crate::{
     C,
     app_env::AppEnv,
     app_error::AppError,
-    db::{ModelAlarm, ModelObliqueStrategy, ModelTimezone},
+    credentials::CredentialSender,
+    db::{ModelAlarm, ModelObliqueStrategy, ModelOutbox, ModelTimezone},
+    glance::{Glance, GlanceData, GlanceSender},
+    notify::Notifier,
     request::PushRequest,
+    ws_messages::Response,
 };
 
 const ONE_SEC: u64 = 1000;
 const TWENTY_FIVE_SEC: Duration = std::time::Duration::from_secs(25);
+/// Number of pushes sent for each alarm, unless dismissed
+const PUSH_TOTAL: u8 = 40;
+
+/// Events generated by the AlarmSchedule, to be forwarded to any connected websocket client
+pub type EventSender = broadcast::Sender<Response>;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CronMessage {
     Reset,
-    AlarmStart(Option<String>),
+    AlarmStart(ModelAlarm),
     AlarmDismiss,
 }
 
 #[derive(Debug)]
 pub struct AlarmSchedule {
-    app_env: AppEnv,
+    event_sx: EventSender,
+    glance: Option<GlanceSender>,
     loop_alarm: Option<JoinHandle<()>>,
     loop_msg: Option<JoinHandle<()>>,
+    notifier: Notifier,
+    /// Index of the most recent push of the ringing alarm
+    push_index: Arc<AtomicU8>,
     rx: Receiver<CronMessage>,
     sqlite: SqlitePool,
     sx: Sender<CronMessage>,
@@ -39,14 +61,19 @@ impl AlarmSchedule {
     pub async fn init(
         sqlite: SqlitePool,
         app_env: AppEnv,
+        event_sx: EventSender,
+        credentials: &CredentialSender,
     ) -> Result<Sender<CronMessage>, AppError> {
         let time_zone = ModelTimezone::get(&sqlite).await.unwrap_or_default();
         let (sx, rx) = tokio::sync::mpsc::channel(128);
 
         let mut alarm_schedule = Self {
-            app_env: C!(app_env),
+            event_sx,
+            glance: Glance::new(&app_env, &sqlite).map(|i| i.spawn(credentials.subscribe())),
             loop_alarm: None,
             loop_msg: None,
+            notifier: Notifier::new(&app_env, &sqlite),
+            push_index: Arc::new(AtomicU8::new(0)),
             rx,
             sqlite,
             sx: C!(sx),
@@ -88,31 +115,97 @@ impl AlarmSchedule {
                 }
                 CronMessage::AlarmDismiss => {
                     if let Some(looper) = self.loop_alarm.as_ref() {
+                        if !looper.is_finished() {
+                            self.send_event(Response::AlarmDismissed {
+                                push_index: self.push_index.load(Ordering::Relaxed),
+                            });
+                        }
                         looper.abort();
                     }
+                    if let Err(e) = ModelOutbox::delete_alarms(&self.sqlite).await {
+                        tracing::error!("{e}");
+                    }
                 }
-                CronMessage::AlarmStart(msg) => {
-                    let sqlite = C!(self.sqlite);
-                    let app_envs = C!(self.app_env);
-                    let msg = Self::get_message(&self.sqlite, msg).await;
+                CronMessage::AlarmStart(alarm) => {
+                    let event_sx = C!(self.event_sx);
+                    let mut notifier = C!(self.notifier);
+                    let alarm_id = alarm.alarm_id;
+                    let msg = Self::get_message(&self.sqlite, alarm.message).await;
+                    let options = alarm.options;
+                    let recipients = alarm.recipients.0;
+                    let push_index = C!(self.push_index);
+                    push_index.store(0, Ordering::Relaxed);
+                    self.send_event(Response::AlarmStarted {
+                        push_total: PUSH_TOTAL,
+                    });
                     self.loop_alarm = Some(tokio::spawn(async move {
-                        for i in 1..=40 {
-                            if let Err(e) = PushRequest::Alarm(i)
-                                .make_request(&app_envs, &sqlite, &msg)
-                                .await
-                            {
+                        for i in 1..=PUSH_TOTAL {
+                            push_index.store(i, Ordering::Relaxed);
+                            let result = notifier
+                                .send(
+                                    &PushRequest::Alarm {
+                                        alarm_id,
+                                        push_index: i,
+                                    },
+                                    &msg,
+                                    &options,
+                                    &recipients,
+                                )
+                                .await;
+                            event_sx
+                                .send(Response::AlarmPush {
+                                    push_index: i,
+                                    push_total: PUSH_TOTAL,
+                                    success: result.is_ok(),
+                                })
+                                .ok();
+                            if let Err(e) = result {
                                 tracing::error!("{e}");
+                                event_sx
+                                    .send(Response::NotifyFailed {
+                                        push_index: i,
+                                        errors: e.to_string(),
+                                    })
+                                    .ok();
+                                if notifier.is_disabled() {
+                                    tracing::error!(
+                                        "every notification channel is disabled, alarm stopped"
+                                    );
+                                    break;
+                                }
+                            }
+                            if i < PUSH_TOTAL {
+                                tokio::time::sleep(TWENTY_FIVE_SEC).await;
                             }
-                            tokio::time::sleep(TWENTY_FIVE_SEC).await;
                         }
+                        event_sx
+                            .send(Response::AlarmRanOut {
+                                push_index: push_index.load(Ordering::Relaxed),
+                            })
+                            .ok();
                     }));
                 }
             }
         }
     }
 
+    /// Send an event to any connected client, there may be none
+    fn send_event(&self, response: Response) {
+        self.event_sx.send(response).ok();
+    }
+
+    /// Update the glance, if configured, with the next alarm, the glance worker itself ignores unchanged data
+    fn update_glance(&self, alarm: Option<&ModelAlarm>) {
+        if let Some(glance) = self.glance.as_ref() {
+            glance.send_replace(GlanceData::from_alarm(alarm));
+        }
+    }
+
     async fn generate_alarm_loop(&mut self) -> Result<(), AppError> {
-        if let Some(alarm) = ModelAlarm::get(&self.sqlite).await? {
+        let alarm = ModelAlarm::get(&self.sqlite).await?;
+        self.update_glance(alarm.as_ref());
+        if let Some(alarm) = alarm {
+            self.send_event(Response::AlarmScheduled(C!(alarm)));
             let tz = C!(self.time_zone);
             let sx = C!(self.sx);
             self.loop_msg = Some(tokio::spawn(async move {
@@ -141,9 +234,7 @@ impl AlarmSchedule {
                 && alarm.minute == current_time.minute()
                 && current_time.second() == 0
             {
-                sx.send(CronMessage::AlarmStart(alarm.message.clone()))
-                    .await
-                    .ok();
+                sx.send(CronMessage::AlarmStart(C!(alarm))).await.ok();
             }
             let to_sleep = ONE_SEC
                 .saturating_sub(u64::try_from(start.elapsed().as_millis()).unwrap_or(ONE_SEC));
@@ -185,4 +276,49 @@ mod tests {
         assert!(set.contains(&result));
         test_cleanup(uuid, Some(sqlite)).await;
     }
+
+    #[tokio::test]
+    // Every stage of an alarm is sent as an event
+    async fn alarm_schedule_events() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        ModelAlarm::add(&sqlite, (6, 15, None).into())
+            .await
+            .unwrap();
+        let (event_sx, mut events) = broadcast::channel(16);
+
+        let sx = AlarmSchedule::init(C!(sqlite), app_envs, event_sx, &CredentialSender::new(None))
+            .await
+            .unwrap();
+        let alarm = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Response::AlarmScheduled(i) if i == alarm
+        ));
+
+        // Nothing is ringing, so nothing to dismiss
+        sx.send(CronMessage::AlarmDismiss).await.unwrap();
+        crate::sleep!(50);
+        assert!(events.try_recv().is_err());
+
+        sx.send(CronMessage::AlarmStart(alarm)).await.unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Response::AlarmStarted { push_total: 40 }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Response::AlarmPush {
+                push_index: 1,
+                push_total: 40,
+                success: true
+            }
+        ));
+
+        sx.send(CronMessage::AlarmDismiss).await.unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Response::AlarmDismissed { push_index: 1 }
+        ));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
 }
@@ -1,21 +1,144 @@
 use jiff::tz::TimeZone;
-use std::{collections::HashMap, env, time::SystemTime};
+use std::{
+    collections::HashMap,
+    env,
+    time::{Duration, SystemTime},
+};
 
-use crate::app_error::AppError;
+use crate::{
+    S, app_error::AppError, backoff::Backoff, db::RequestCount, notify::Channel,
+    request::PushRequest,
+};
 
 type EnvHashMap = HashMap<String, String>;
 
+/// Name given to the recipient generated from `TOKEN_USER`
+pub const DEFAULT_RECIPIENT: &str = "default";
+
+/// A single Pushover user, and optionally the devices to limit pushes to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub name: String,
+    pub user: String,
+    pub devices: Vec<String>,
+}
+
+impl Recipient {
+    /// Recipient names are used in ws messages, so keep them simple, 1-32 chars of [A-Za-z0-9_-].
+    /// Shared by env parsing and ws message validation
+    pub fn valid_name(name: &str) -> bool {
+        (1..=32).contains(&name.len())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+}
+
+/// Maximum number of requests allowed in the past hour, and in the past day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub hour: i64,
+    pub day: i64,
+}
+
+impl RateLimit {
+    /// Error if either window has been used up
+    pub const fn check(self, count: RequestCount) -> Result<(), AppError> {
+        if count.hour >= self.hour {
+            Err(AppError::TooManyRequests(count.hour))
+        } else if count.day >= self.day {
+            Err(AppError::TooManyRequestsDay(count.day))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Rate limits for a single channel, alarm and test requests are limited separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelLimits {
+    pub alarm: RateLimit,
+    pub test: RateLimit,
+}
+
+impl Default for ChannelLimits {
+    fn default() -> Self {
+        Self {
+            alarm: RateLimit { hour: 60, day: 240 },
+            test: RateLimit { hour: 10, day: 50 },
+        }
+    }
+}
+
+/// Default Telegram Bot API url, can be overridden with `TELEGRAM_URL`
+const TELEGRAM_URL: &str = "https://api.telegram.org";
+
+/// Default number of days, and rows, of requests to keep, can be overridden with `REQUEST_RETENTION_DAYS` & `REQUEST_RETENTION_ROWS`
+const REQUEST_RETENTION_DAYS: u64 = 90;
+const REQUEST_RETENTION_ROWS: u64 = 50_000;
+
+/// Default Pushover api base url, can be overridden with `PUSHOVER_URL`
+const PUSHOVER_URL: &str = "https://api.pushover.net";
+
+/// Default Pushover request timeout, in milliseconds, can be overridden with `PUSHOVER_TIMEOUT_MS`
+const PUSHOVER_TIMEOUT_MS: u64 = 10_000;
+
+/// Default minimum time, in seconds, between glance updates, can be overridden with `GLANCE_INTERVAL`
+const GLANCE_INTERVAL: u64 = 300;
+
+/// Default age, in seconds, after which undelivered outbox entries are dropped, can be overridden with `OUTBOX_MAX_AGE`
+const OUTBOX_MAX_AGE: u64 = 300;
+
+/// Default time, in seconds, a single ws command can take before it's abandoned, can be overridden with `WS_COMMAND_TIMEOUT`
+const WS_COMMAND_TIMEOUT: u64 = 60;
+
+/// Default age, in seconds, after which events waiting for a ws connection are dropped, can be overridden with `WS_EVENT_MAX_AGE`
+const WS_EVENT_MAX_AGE: u64 = 300;
+
+/// Default number of ws commands that can be waiting to be processed, can be overridden with `WS_QUEUE_DEPTH`
+const WS_QUEUE_DEPTH: usize = 16;
+
+/// Default base delay, in milliseconds, before retrying a failed ws connection, can be overridden with `WS_RECONNECT_BASE_MS`
+const WS_RECONNECT_BASE_MS: u64 = 1_000;
+
+/// Default base delay, in milliseconds, before retrying after the ws auth token was refused, can be overridden with `WS_RECONNECT_AUTH_BASE_MS`
+const WS_RECONNECT_AUTH_BASE_MS: u64 = 10_000;
+
+/// Default maximum delay, in milliseconds, between ws connection attempts, can be overridden with `WS_RECONNECT_MAX_MS`
+const WS_RECONNECT_MAX_MS: u64 = 300_000;
+
 #[derive(Debug, Clone)]
 pub struct AppEnv {
+    pub glance_interval: u64,
+    pub glance_recipient: Option<String>,
     pub location_sqlite: String,
+    pub limits: HashMap<Channel, ChannelLimits>,
     pub log_level: tracing::Level,
+    pub matrix_access_token: Option<String>,
+    pub matrix_homeserver: Option<String>,
+    pub matrix_room_id: Option<String>,
+    pub notify_chain: Vec<Channel>,
+    pub outbox_max_age: u64,
+    pub pushover_timeout_ms: u64,
+    pub pushover_url: String,
+    pub pushover_validate_strict: bool,
+    pub recipients: Vec<Recipient>,
+    pub request_retention_days: u64,
+    pub request_retention_rows: u64,
     pub start_time: SystemTime,
+    pub telegram_chat_id: Option<String>,
+    pub telegram_token: Option<String>,
+    pub telegram_url: String,
     pub token_app: String,
-    pub token_user: String,
     pub timezone: TimeZone,
     pub ws_address: String,
     pub ws_apikey: String,
+    pub ws_command_timeout: u64,
+    pub ws_event_max_age: u64,
     pub ws_password: String,
+    pub ws_queue_depth: usize,
+    pub ws_reconnect: Backoff,
+    pub ws_reconnect_auth: Backoff,
     pub ws_token_address: String,
 }
 
@@ -48,6 +171,235 @@ impl AppEnv {
             })
     }
 
+    /// Parse an optional env, an empty value is treated as missing
+    fn parse_optional(key: &str, map: &EnvHashMap) -> Option<String> {
+        map.get(key).filter(|value| !value.is_empty()).cloned()
+    }
+
+    /// Parse an optional number, using the default when missing, but erroring if set and invalid
+    fn parse_number<T: std::str::FromStr>(
+        key: &str,
+        map: &EnvHashMap,
+        default: T,
+    ) -> Result<T, AppError> {
+        Self::parse_optional(key, map).map_or(Ok(default), |value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| AppError::EnvInvalid(key.into()))
+        })
+    }
+
+    /// Retention needs to be at least a day, else the daily rate limit can't be enforced
+    fn parse_retention(map: &EnvHashMap) -> Result<(u64, u64), AppError> {
+        let days = Self::parse_number("REQUEST_RETENTION_DAYS", map, REQUEST_RETENTION_DAYS)?;
+        let rows = Self::parse_number("REQUEST_RETENTION_ROWS", map, REQUEST_RETENTION_ROWS)?;
+        if days == 0 {
+            return Err(AppError::EnvInvalid(S!("REQUEST_RETENTION_DAYS")));
+        }
+        if rows == 0 {
+            return Err(AppError::EnvInvalid(S!("REQUEST_RETENTION_ROWS")));
+        }
+        Ok((days, rows))
+    }
+
+    /// The api base url needs to be a valid http(s) url, and a zero timeout would fail every request
+    fn parse_pushover(map: &EnvHashMap) -> Result<(String, u64), AppError> {
+        let url = Self::parse_optional("PUSHOVER_URL", map).unwrap_or_else(|| S!(PUSHOVER_URL));
+        if !url::Url::parse(&url).is_ok_and(|i| ["http", "https"].contains(&i.scheme())) {
+            return Err(AppError::EnvInvalid(S!("PUSHOVER_URL")));
+        }
+        let timeout = Self::parse_number("PUSHOVER_TIMEOUT_MS", map, PUSHOVER_TIMEOUT_MS)?;
+        if timeout == 0 {
+            return Err(AppError::EnvInvalid(S!("PUSHOVER_TIMEOUT_MS")));
+        }
+        Ok((url, timeout))
+    }
+
+    /// The glance recipient, if set, must be one of the configured recipients, and updates can't be unlimited
+    fn parse_glance(
+        map: &EnvHashMap,
+        recipients: &[Recipient],
+    ) -> Result<(u64, Option<String>), AppError> {
+        let recipient = Self::parse_optional("GLANCE_RECIPIENT", map);
+        if let Some(name) = recipient.as_ref()
+            && !recipients.iter().any(|i| &i.name == name)
+        {
+            return Err(AppError::EnvInvalid(S!("GLANCE_RECIPIENT")));
+        }
+        let interval = Self::parse_number("GLANCE_INTERVAL", map, GLANCE_INTERVAL)?;
+        if interval == 0 {
+            return Err(AppError::EnvInvalid(S!("GLANCE_INTERVAL")));
+        }
+        Ok((interval, recipient))
+    }
+
+    /// Commands are processed one at a time, so neither the timeout or the queue depth can be zero
+    fn parse_ws_commands(map: &EnvHashMap) -> Result<(u64, usize), AppError> {
+        let timeout = Self::parse_number("WS_COMMAND_TIMEOUT", map, WS_COMMAND_TIMEOUT)?;
+        if timeout == 0 {
+            return Err(AppError::EnvInvalid(S!("WS_COMMAND_TIMEOUT")));
+        }
+        let depth = Self::parse_number("WS_QUEUE_DEPTH", map, WS_QUEUE_DEPTH)?;
+        if depth == 0 {
+            return Err(AppError::EnvInvalid(S!("WS_QUEUE_DEPTH")));
+        }
+        Ok((timeout, depth))
+    }
+
+    /// Backoff for ws connection failures, and a separate one for auth failures, which are unlikely to fix themselves quickly.
+    /// A zero base would retry in a tight loop, and neither base can be above the maximum
+    fn parse_ws_reconnect(map: &EnvHashMap) -> Result<(Backoff, Backoff), AppError> {
+        let max = Self::parse_number("WS_RECONNECT_MAX_MS", map, WS_RECONNECT_MAX_MS)?;
+        let base = Self::parse_number("WS_RECONNECT_BASE_MS", map, WS_RECONNECT_BASE_MS)?;
+        if base == 0 || base > max {
+            return Err(AppError::EnvInvalid(S!("WS_RECONNECT_BASE_MS")));
+        }
+        let auth_base =
+            Self::parse_number("WS_RECONNECT_AUTH_BASE_MS", map, WS_RECONNECT_AUTH_BASE_MS)?;
+        if auth_base == 0 || auth_base > max {
+            return Err(AppError::EnvInvalid(S!("WS_RECONNECT_AUTH_BASE_MS")));
+        }
+        let max = Duration::from_millis(max);
+        Ok((
+            Backoff::new(Duration::from_millis(base), max),
+            Backoff::new(Duration::from_millis(auth_base), max),
+        ))
+    }
+
+    /// Split a list on the given separator, ignoring empty values
+    fn split_list(input: &str, separator: char) -> Vec<String> {
+        input
+            .split(separator)
+            .map(str::trim)
+            .filter(|i| !i.is_empty())
+            .map(std::borrow::ToOwned::to_owned)
+            .collect()
+    }
+
+    /// The default recipient is generated from `TOKEN_USER`, with an optional comma separated `TOKEN_USER_DEVICES`.
+    /// Extra recipients are given in `PUSHOVER_RECIPIENTS`, as a comma separated list of `name:user_key[:device+device]`
+    fn parse_recipients(map: &EnvHashMap) -> Result<Vec<Recipient>, AppError> {
+        let mut recipients = vec![Recipient {
+            name: DEFAULT_RECIPIENT.to_owned(),
+            user: Self::parse_string("TOKEN_USER", map)?,
+            devices: Self::parse_optional("TOKEN_USER_DEVICES", map)
+                .map(|devices| Self::split_list(&devices, ','))
+                .unwrap_or_default(),
+        }];
+
+        if let Some(extra) = Self::parse_optional("PUSHOVER_RECIPIENTS", map) {
+            for entry in Self::split_list(&extra, ',') {
+                let mut parts = entry.split(':').map(str::trim);
+                let (Some(name), Some(user)) = (parts.next(), parts.next()) else {
+                    return Err(AppError::RecipientInvalid(entry));
+                };
+                let devices = parts
+                    .next()
+                    .map(|devices| Self::split_list(devices, '+'))
+                    .unwrap_or_default();
+                if !Recipient::valid_name(name)
+                    || user.is_empty()
+                    || parts.next().is_some()
+                    || recipients.iter().any(|i| i.name == name)
+                {
+                    return Err(AppError::RecipientInvalid(entry));
+                }
+                recipients.push(Recipient {
+                    name: name.to_owned(),
+                    user: user.to_owned(),
+                    devices,
+                });
+            }
+        }
+        Ok(recipients)
+    }
+
+    /// `NOTIFY_CHAIN` is a comma separated, ordered, list of channels, each of which need to be configured.
+    /// When not set, defaults to pushover, followed by telegram and then matrix, if they have been configured
+    fn parse_notify_chain(map: &EnvHashMap) -> Result<Vec<Channel>, AppError> {
+        let telegram = Self::parse_optional("TELEGRAM_TOKEN", map).is_some()
+            && Self::parse_optional("TELEGRAM_CHAT_ID", map).is_some();
+        let matrix = Self::parse_optional("MATRIX_ACCESS_TOKEN", map).is_some()
+            && Self::parse_optional("MATRIX_HOMESERVER", map).is_some()
+            && Self::parse_optional("MATRIX_ROOM_ID", map).is_some();
+        let configured = |channel: Channel| match channel {
+            Channel::Pushover => true,
+            Channel::Telegram => telegram,
+            Channel::Matrix => matrix,
+        };
+
+        let Some(chain) = Self::parse_optional("NOTIFY_CHAIN", map) else {
+            return Ok([Channel::Pushover, Channel::Telegram, Channel::Matrix]
+                .into_iter()
+                .filter(|i| configured(*i))
+                .collect());
+        };
+
+        let mut output = vec![];
+        for name in Self::split_list(&chain, ',') {
+            let channel = name.parse::<Channel>()?;
+            if !configured(channel) || output.contains(&channel) {
+                return Err(AppError::ChainInvalid(name));
+            }
+            output.push(channel);
+        }
+        if output.is_empty() {
+            return Err(AppError::ChainInvalid(chain));
+        }
+        Ok(output)
+    }
+
+    /// Each limit can be set with `LIMIT_<CHANNEL>_<ALARM|TEST>_<HOUR|DAY>`, e.g. `LIMIT_PUSHOVER_TEST_HOUR`
+    fn parse_limits(map: &EnvHashMap) -> Result<HashMap<Channel, ChannelLimits>, AppError> {
+        let mut output = HashMap::new();
+        for channel in [Channel::Pushover, Channel::Telegram, Channel::Matrix] {
+            let default = ChannelLimits::default();
+            let prefix = format!("LIMIT_{}", channel.as_str().to_uppercase());
+            let parse = |key: &str, default: i64| {
+                Self::parse_number(&format!("{prefix}_{key}"), map, default)
+            };
+            output.insert(
+                channel,
+                ChannelLimits {
+                    alarm: RateLimit {
+                        hour: parse("ALARM_HOUR", default.alarm.hour)?,
+                        day: parse("ALARM_DAY", default.alarm.day)?,
+                    },
+                    test: RateLimit {
+                        hour: parse("TEST_HOUR", default.test.hour)?,
+                        day: parse("TEST_DAY", default.test.day)?,
+                    },
+                },
+            );
+        }
+        if output
+            .values()
+            .flat_map(|i| [i.alarm, i.test])
+            .any(|i| i.hour < 0 || i.day < 0)
+        {
+            return Err(AppError::EnvInvalid(S!("LIMIT")));
+        }
+        Ok(output)
+    }
+
+    /// The rate limit for a given channel and type of request
+    pub fn limit(&self, channel: Channel, push_request: &PushRequest) -> RateLimit {
+        let limits = self.limits.get(&channel).copied().unwrap_or_default();
+        match push_request {
+            PushRequest::Alarm { .. } => limits.alarm,
+            PushRequest::TestRequest => limits.test,
+        }
+    }
+
+    /// Get the recipients matching the given names, an empty list means all recipients
+    pub fn get_recipients(&self, names: &[String]) -> Vec<&Recipient> {
+        self.recipients
+            .iter()
+            .filter(|i| names.is_empty() || names.contains(&i.name))
+            .collect()
+    }
+
     /// Check that a given timezone is valid, else return UTC
     fn parse_timezone(map: &EnvHashMap) -> TimeZone {
         map.get("TZ").map_or(TimeZone::UTC, |s| {
@@ -72,16 +424,44 @@ impl AppEnv {
             .map(|i| (i.0, i.1))
             .collect::<HashMap<String, String>>();
 
+        let (request_retention_days, request_retention_rows) = Self::parse_retention(&env_map)?;
+        let (pushover_url, pushover_timeout_ms) = Self::parse_pushover(&env_map)?;
+        let recipients = Self::parse_recipients(&env_map)?;
+        let (glance_interval, glance_recipient) = Self::parse_glance(&env_map, &recipients)?;
+        let (ws_command_timeout, ws_queue_depth) = Self::parse_ws_commands(&env_map)?;
+        let (ws_reconnect, ws_reconnect_auth) = Self::parse_ws_reconnect(&env_map)?;
         Ok(Self {
+            glance_interval,
+            glance_recipient,
             location_sqlite: Self::parse_db_name("LOCATION_SQLITE", &env_map)?,
+            limits: Self::parse_limits(&env_map)?,
             log_level: Self::parse_log(&env_map),
+            recipients,
+            request_retention_days,
+            request_retention_rows,
+            matrix_access_token: Self::parse_optional("MATRIX_ACCESS_TOKEN", &env_map),
+            matrix_homeserver: Self::parse_optional("MATRIX_HOMESERVER", &env_map),
+            matrix_room_id: Self::parse_optional("MATRIX_ROOM_ID", &env_map),
+            notify_chain: Self::parse_notify_chain(&env_map)?,
+            outbox_max_age: Self::parse_number("OUTBOX_MAX_AGE", &env_map, OUTBOX_MAX_AGE)?,
+            pushover_timeout_ms,
+            pushover_url,
+            pushover_validate_strict: Self::parse_boolean("PUSHOVER_VALIDATE_STRICT", &env_map),
             start_time: SystemTime::now(),
+            telegram_chat_id: Self::parse_optional("TELEGRAM_CHAT_ID", &env_map),
+            telegram_token: Self::parse_optional("TELEGRAM_TOKEN", &env_map),
+            telegram_url: Self::parse_optional("TELEGRAM_URL", &env_map)
+                .unwrap_or_else(|| TELEGRAM_URL.to_owned()),
             timezone: Self::parse_timezone(&env_map),
             token_app: Self::parse_string("TOKEN_APP", &env_map)?,
-            token_user: Self::parse_string("TOKEN_USER", &env_map)?,
             ws_address: Self::parse_string("WS_ADDRESS", &env_map)?,
             ws_apikey: Self::parse_string("WS_APIKEY", &env_map)?,
+            ws_command_timeout,
+            ws_event_max_age: Self::parse_number("WS_EVENT_MAX_AGE", &env_map, WS_EVENT_MAX_AGE)?,
             ws_password: Self::parse_string("WS_PASSWORD", &env_map)?,
+            ws_queue_depth,
+            ws_reconnect,
+            ws_reconnect_auth,
             ws_token_address: Self::parse_string("WS_TOKEN_ADDRESS", &env_map)?,
         })
     }
@@ -141,6 +521,422 @@ mod tests {
         assert_eq!(result, "/alarms.db");
     }
 
+    #[test]
+    fn env_parse_optional() {
+        let map = HashMap::from([
+            (S!("TELEGRAM_TOKEN"), S!("token")),
+            (S!("TELEGRAM_CHAT_ID"), S!("")),
+        ]);
+
+        assert_eq!(
+            AppEnv::parse_optional("TELEGRAM_TOKEN", &map),
+            Some(S!("token"))
+        );
+        assert!(AppEnv::parse_optional("TELEGRAM_CHAT_ID", &map).is_none());
+        assert!(AppEnv::parse_optional("TELEGRAM_URL", &map).is_none());
+    }
+
+    #[test]
+    fn env_parse_number() {
+        let mut map = HashMap::new();
+        assert_eq!(
+            AppEnv::parse_number("OUTBOX_MAX_AGE", &map, 300u64).unwrap(),
+            300
+        );
+
+        map.insert(S!("OUTBOX_MAX_AGE"), S!(""));
+        assert_eq!(
+            AppEnv::parse_number("OUTBOX_MAX_AGE", &map, 300u64).unwrap(),
+            300
+        );
+
+        map.insert(S!("OUTBOX_MAX_AGE"), S!(" 60 "));
+        assert_eq!(
+            AppEnv::parse_number("OUTBOX_MAX_AGE", &map, 300u64).unwrap(),
+            60
+        );
+
+        for value in ["-1", "sixty", "1.5"] {
+            map.insert(S!("OUTBOX_MAX_AGE"), S!(value));
+            let result = AppEnv::parse_number("OUTBOX_MAX_AGE", &map, 300u64);
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "invalid env: 'OUTBOX_MAX_AGE'"
+            );
+        }
+    }
+
+    #[test]
+    fn env_parse_retention() {
+        let mut map = HashMap::new();
+        assert_eq!(
+            AppEnv::parse_retention(&map).unwrap(),
+            (REQUEST_RETENTION_DAYS, REQUEST_RETENTION_ROWS)
+        );
+
+        map.insert(S!("REQUEST_RETENTION_DAYS"), S!("7"));
+        map.insert(S!("REQUEST_RETENTION_ROWS"), S!("1000"));
+        assert_eq!(AppEnv::parse_retention(&map).unwrap(), (7, 1000));
+
+        map.insert(S!("REQUEST_RETENTION_ROWS"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_retention(&map).unwrap_err().to_string(),
+            "invalid env: 'REQUEST_RETENTION_ROWS'"
+        );
+
+        map.insert(S!("REQUEST_RETENTION_DAYS"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_retention(&map).unwrap_err().to_string(),
+            "invalid env: 'REQUEST_RETENTION_DAYS'"
+        );
+    }
+
+    #[test]
+    fn env_parse_pushover() {
+        let mut map = HashMap::new();
+        assert_eq!(
+            AppEnv::parse_pushover(&map).unwrap(),
+            (S!(PUSHOVER_URL), PUSHOVER_TIMEOUT_MS)
+        );
+
+        map.insert(S!("PUSHOVER_URL"), S!("http://127.0.0.1:8080"));
+        map.insert(S!("PUSHOVER_TIMEOUT_MS"), S!("2500"));
+        assert_eq!(
+            AppEnv::parse_pushover(&map).unwrap(),
+            (S!("http://127.0.0.1:8080"), 2500)
+        );
+
+        map.insert(S!("PUSHOVER_TIMEOUT_MS"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_pushover(&map).unwrap_err().to_string(),
+            "invalid env: 'PUSHOVER_TIMEOUT_MS'"
+        );
+
+        for url in ["api.pushover.net", "ftp://api.pushover.net"] {
+            map.insert(S!("PUSHOVER_URL"), S!(url));
+            assert_eq!(
+                AppEnv::parse_pushover(&map).unwrap_err().to_string(),
+                "invalid env: 'PUSHOVER_URL'"
+            );
+        }
+    }
+
+    #[test]
+    fn env_parse_glance() {
+        let recipients = vec![Recipient {
+            name: S!("jack"),
+            user: S!("user_jack"),
+            devices: vec![],
+        }];
+        let mut map = HashMap::new();
+        assert_eq!(
+            AppEnv::parse_glance(&map, &recipients).unwrap(),
+            (GLANCE_INTERVAL, None)
+        );
+
+        map.insert(S!("GLANCE_RECIPIENT"), S!("jack"));
+        map.insert(S!("GLANCE_INTERVAL"), S!("60"));
+        assert_eq!(
+            AppEnv::parse_glance(&map, &recipients).unwrap(),
+            (60, Some(S!("jack")))
+        );
+
+        map.insert(S!("GLANCE_INTERVAL"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_glance(&map, &recipients)
+                .unwrap_err()
+                .to_string(),
+            "invalid env: 'GLANCE_INTERVAL'"
+        );
+
+        map.insert(S!("GLANCE_RECIPIENT"), S!("sam"));
+        assert_eq!(
+            AppEnv::parse_glance(&map, &recipients)
+                .unwrap_err()
+                .to_string(),
+            "invalid env: 'GLANCE_RECIPIENT'"
+        );
+    }
+
+    #[test]
+    fn env_parse_ws_commands() {
+        let mut map = HashMap::new();
+        assert_eq!(
+            AppEnv::parse_ws_commands(&map).unwrap(),
+            (WS_COMMAND_TIMEOUT, WS_QUEUE_DEPTH)
+        );
+
+        map.insert(S!("WS_COMMAND_TIMEOUT"), S!("5"));
+        map.insert(S!("WS_QUEUE_DEPTH"), S!("2"));
+        assert_eq!(AppEnv::parse_ws_commands(&map).unwrap(), (5, 2));
+
+        map.insert(S!("WS_QUEUE_DEPTH"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_ws_commands(&map).unwrap_err().to_string(),
+            "invalid env: 'WS_QUEUE_DEPTH'"
+        );
+
+        map.insert(S!("WS_COMMAND_TIMEOUT"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_ws_commands(&map).unwrap_err().to_string(),
+            "invalid env: 'WS_COMMAND_TIMEOUT'"
+        );
+    }
+
+    #[test]
+    fn env_parse_ws_reconnect() {
+        let mut map = HashMap::new();
+        let (reconnect, auth) = AppEnv::parse_ws_reconnect(&map).unwrap();
+        assert_eq!(
+            reconnect.ceiling(1),
+            Duration::from_millis(WS_RECONNECT_BASE_MS)
+        );
+        assert_eq!(
+            auth.ceiling(1),
+            Duration::from_millis(WS_RECONNECT_AUTH_BASE_MS)
+        );
+        assert_eq!(
+            reconnect.ceiling(u32::MAX),
+            Duration::from_millis(WS_RECONNECT_MAX_MS)
+        );
+        assert_eq!(
+            auth.ceiling(u32::MAX),
+            Duration::from_millis(WS_RECONNECT_MAX_MS)
+        );
+
+        map.insert(S!("WS_RECONNECT_BASE_MS"), S!("100"));
+        map.insert(S!("WS_RECONNECT_AUTH_BASE_MS"), S!("200"));
+        map.insert(S!("WS_RECONNECT_MAX_MS"), S!("1000"));
+        let (reconnect, auth) = AppEnv::parse_ws_reconnect(&map).unwrap();
+        assert_eq!(reconnect.ceiling(2), Duration::from_millis(200));
+        assert_eq!(auth.ceiling(2), Duration::from_millis(400));
+        assert_eq!(auth.ceiling(4), Duration::from_secs(1));
+
+        map.insert(S!("WS_RECONNECT_AUTH_BASE_MS"), S!("2000"));
+        assert_eq!(
+            AppEnv::parse_ws_reconnect(&map).unwrap_err().to_string(),
+            "invalid env: 'WS_RECONNECT_AUTH_BASE_MS'"
+        );
+
+        map.insert(S!("WS_RECONNECT_BASE_MS"), S!("0"));
+        assert_eq!(
+            AppEnv::parse_ws_reconnect(&map).unwrap_err().to_string(),
+            "invalid env: 'WS_RECONNECT_BASE_MS'"
+        );
+    }
+
+    #[test]
+    fn env_parse_limits() {
+        let mut map = HashMap::new();
+        let result = AppEnv::parse_limits(&map).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result.values().all(|i| *i == ChannelLimits::default()));
+
+        map.insert(S!("LIMIT_PUSHOVER_TEST_HOUR"), S!("5"));
+        map.insert(S!("LIMIT_MATRIX_ALARM_DAY"), S!("1000"));
+        let result = AppEnv::parse_limits(&map).unwrap();
+        assert_eq!(
+            result[&Channel::Pushover].test,
+            RateLimit { hour: 5, day: 50 }
+        );
+        assert_eq!(
+            result[&Channel::Matrix].alarm,
+            RateLimit {
+                hour: 60,
+                day: 1000
+            }
+        );
+        assert_eq!(result[&Channel::Telegram], ChannelLimits::default());
+
+        map.insert(S!("LIMIT_TELEGRAM_TEST_DAY"), S!("ten"));
+        let result = AppEnv::parse_limits(&map);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "invalid env: 'LIMIT_TELEGRAM_TEST_DAY'"
+        );
+
+        map.insert(S!("LIMIT_TELEGRAM_TEST_DAY"), S!("-1"));
+        let result = AppEnv::parse_limits(&map);
+        assert_eq!(result.unwrap_err().to_string(), "invalid env: 'LIMIT'");
+    }
+
+    #[test]
+    fn env_rate_limit_check() {
+        let limit = RateLimit { hour: 10, day: 20 };
+        assert!(limit.check(RequestCount { hour: 9, day: 19 }).is_ok());
+        assert_eq!(
+            limit
+                .check(RequestCount { hour: 10, day: 10 })
+                .unwrap_err()
+                .to_string(),
+            "Too many requests made in the past hour: 10"
+        );
+        assert_eq!(
+            limit
+                .check(RequestCount { hour: 1, day: 20 })
+                .unwrap_err()
+                .to_string(),
+            "Too many requests made in the past day: 20"
+        );
+    }
+
+    #[test]
+    fn env_parse_recipients_ok() {
+        let map = HashMap::from([(S!("TOKEN_USER"), S!("user_default"))]);
+
+        let result = AppEnv::parse_recipients(&map).unwrap();
+
+        assert_eq!(
+            result,
+            vec![Recipient {
+                name: S!(DEFAULT_RECIPIENT),
+                user: S!("user_default"),
+                devices: vec![]
+            }]
+        );
+
+        let map = HashMap::from([
+            (S!("TOKEN_USER"), S!("user_default")),
+            (S!("TOKEN_USER_DEVICES"), S!("phone, tablet")),
+            (
+                S!("PUSHOVER_RECIPIENTS"),
+                S!("jack:user_jack:phone+watch, sam:user_sam"),
+            ),
+        ]);
+
+        let result = AppEnv::parse_recipients(&map).unwrap();
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].devices, vec![S!("phone"), S!("tablet")]);
+        assert_eq!(
+            result[1],
+            Recipient {
+                name: S!("jack"),
+                user: S!("user_jack"),
+                devices: vec![S!("phone"), S!("watch")]
+            }
+        );
+        assert_eq!(
+            result[2],
+            Recipient {
+                name: S!("sam"),
+                user: S!("user_sam"),
+                devices: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn env_parse_recipients_err() {
+        let result = AppEnv::parse_recipients(&HashMap::new());
+        assert_eq!(result.unwrap_err().to_string(), "missing env: 'TOKEN_USER'");
+
+        let test = |recipients: &str| {
+            let map = HashMap::from([
+                (S!("TOKEN_USER"), S!("user_default")),
+                (S!("PUSHOVER_RECIPIENTS"), S!(recipients)),
+            ]);
+            let result = AppEnv::parse_recipients(&map);
+            assert!(matches!(result, Err(AppError::RecipientInvalid(_))));
+        };
+
+        // No user key
+        test("jack");
+        test("jack:");
+        // Invalid name
+        test("ja ck:user_jack");
+        test(":user_jack");
+        // Duplicate names
+        test("default:user_jack");
+        test("jack:user_jack,jack:user_sam");
+        // Too many parts
+        test("jack:user_jack:phone:watch");
+    }
+
+    #[test]
+    fn env_parse_notify_chain_ok() {
+        let telegram = [
+            (S!("TELEGRAM_TOKEN"), S!("token")),
+            (S!("TELEGRAM_CHAT_ID"), S!("1234")),
+        ];
+        let matrix = [
+            (S!("MATRIX_ACCESS_TOKEN"), S!("token")),
+            (S!("MATRIX_HOMESERVER"), S!("https://matrix.example.com")),
+            (S!("MATRIX_ROOM_ID"), S!("!abc:example.com")),
+        ];
+
+        let result = AppEnv::parse_notify_chain(&HashMap::new()).unwrap();
+        assert_eq!(result, vec![Channel::Pushover]);
+
+        let map = HashMap::from(telegram.clone());
+        let result = AppEnv::parse_notify_chain(&map).unwrap();
+        assert_eq!(result, vec![Channel::Pushover, Channel::Telegram]);
+
+        let mut map = HashMap::from(matrix);
+        map.extend(telegram);
+        let result = AppEnv::parse_notify_chain(&map).unwrap();
+        assert_eq!(
+            result,
+            vec![Channel::Pushover, Channel::Telegram, Channel::Matrix]
+        );
+
+        map.insert(S!("NOTIFY_CHAIN"), S!("matrix, pushover"));
+        let result = AppEnv::parse_notify_chain(&map).unwrap();
+        assert_eq!(result, vec![Channel::Matrix, Channel::Pushover]);
+    }
+
+    #[test]
+    fn env_parse_notify_chain_err() {
+        let test = |chain: &str| {
+            let map = HashMap::from([
+                (S!("NOTIFY_CHAIN"), S!(chain)),
+                (S!("TELEGRAM_TOKEN"), S!("token")),
+                (S!("TELEGRAM_CHAT_ID"), S!("1234")),
+            ]);
+            let result = AppEnv::parse_notify_chain(&map);
+            assert!(matches!(result, Err(AppError::ChainInvalid(_))));
+        };
+
+        // Unknown channel
+        test("pushover,email");
+        // Not configured
+        test("pushover,matrix");
+        // Duplicate
+        test("pushover,telegram,pushover");
+        // Empty
+        test(",");
+    }
+
+    #[test]
+    fn env_recipient_valid_name() {
+        for name in ["a", "default", "Sam_2", "a-b", &"a".repeat(32)] {
+            assert!(Recipient::valid_name(name), "{name}");
+        }
+        for name in ["", &"a".repeat(33), "a b", "a:b", "a+b", "é"] {
+            assert!(!Recipient::valid_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn env_get_recipients() {
+        let mut app_envs = crate::tests::gen_app_envs(uuid::Uuid::new_v4());
+        app_envs.recipients.push(Recipient {
+            name: S!("jack"),
+            user: S!("user_jack"),
+            devices: vec![],
+        });
+
+        let result = app_envs.get_recipients(&[]);
+        assert_eq!(result.len(), 2);
+
+        let result = app_envs.get_recipients(&[S!("jack")]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].user, "user_jack");
+
+        let result = app_envs.get_recipients(&[S!("unknown")]);
+        assert!(result.is_empty());
+    }
+
     #[tokio::test]
     async fn env_parse_boolean_ok() {
         let mut map = HashMap::new();
@@ -1,21 +1,144 @@
 use thiserror::Error;
 
+use crate::C;
+
+/// Typed failures from the Pushover messages api
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PushoverError {
+    #[error("invalid application token: {0}")]
+    InvalidToken(String),
+    #[error("invalid user: {0}")]
+    InvalidUser(String),
+    #[error("rate limited, resets at {0:?}")]
+    RateLimited(Option<i64>),
+    #[error("rejected, status {status}: {errors}")]
+    Rejected { status: u16, errors: String },
+    #[error("server error, status {0}")]
+    Server(u16),
+}
+
 #[derive(Debug, Error)]
 pub enum AppError {
+    #[error("Invalid notification channel: '{0}'")]
+    ChainInvalid(String),
+    #[error("Invalid Pushover credentials: {0}")]
+    CredentialsInvalid(String),
     #[error("'{0}' - sql file should end '.db'")]
     DbNameInvalid(String),
+    #[error("invalid env: '{0}'")]
+    EnvInvalid(String),
+    #[error("Matrix: {0}")]
+    Matrix(String),
     #[error("missing env: '{0}'")]
     MissingEnv(String),
+    #[error("All notification channels failed: {0}")]
+    NotifyFailed(String),
+    #[error("Pushover: {0}")]
+    Pushover(#[from] PushoverError),
+    #[error("Invalid recipient: '{0}'")]
+    RecipientInvalid(String),
     #[error("Reqwest Error")]
     Reqwest(#[from] reqwest::Error),
+    #[error("Telegram: {0}")]
+    Telegram(String),
     #[error("Internal Database Error: {0}")]
     Sqlx(#[from] sqlx::Error),
     #[error("WS Connect: {0}")]
     TungsteniteConnect(String),
     #[error("Url parsing error: {0}")]
     Url(#[from] url::ParseError),
+    #[error("WS Auth: {0}")]
+    WsAuth(String),
     #[error("Invalid WS Status Code")]
     WsStatus,
     #[error("Too many requests made in the past hour: {0}")]
     TooManyRequests(i64),
+    #[error("Too many requests made in the past day: {0}")]
+    TooManyRequestsDay(i64),
+}
+
+impl AppError {
+    /// Errors which will fail in exactly the same way on every attempt, so there's no point in retrying
+    pub const fn is_fatal(&self) -> bool {
+        matches!(self, Self::Pushover(PushoverError::InvalidToken(_)))
+    }
+
+    /// Transient failures, connection errors, timeouts, and server errors, which may succeed if tried again.
+    /// A Pushover rate-limit isn't, the app quota stays exhausted until `X-Limit-App-Reset`, so a retry would only burn another request
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => e.is_connect() || e.is_timeout(),
+            Self::Pushover(e) => matches!(e, PushoverError::Server(_)),
+            _ => false,
+        }
+    }
+
+    /// The Pushover app quota has been used up, says nothing about whether the credentials are valid
+    pub const fn is_rate_limited(&self) -> bool {
+        matches!(self, Self::Pushover(PushoverError::RateLimited(_)))
+    }
+
+    /// The underlying error message, `Reqwest` is displayed without its source.
+    /// Request urls can contain credentials, as query params or in the path, so only the origin is kept
+    pub fn detail(&self) -> String {
+        match self {
+            Self::Reqwest(e) => {
+                let detail = e.to_string();
+                e.url().map_or_else(
+                    || C!(detail),
+                    |url| detail.replace(url.as_str(), &url.origin().ascii_serialization()),
+                )
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// app_error
+///
+/// cargo watch -q -c -w src/ -x 'test app_error -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::S;
+
+    #[test]
+    fn app_error_is_retryable() {
+        assert!(AppError::Pushover(PushoverError::Server(502)).is_retryable());
+        assert!(!AppError::Pushover(PushoverError::RateLimited(None)).is_retryable());
+        assert!(AppError::Pushover(PushoverError::RateLimited(None)).is_rate_limited());
+        assert!(!AppError::Pushover(PushoverError::Server(502)).is_rate_limited());
+        assert!(!AppError::Pushover(PushoverError::InvalidToken(S!())).is_retryable());
+        assert!(!AppError::Pushover(PushoverError::InvalidUser(S!())).is_retryable());
+        assert!(
+            !AppError::Pushover(PushoverError::Rejected {
+                status: 400,
+                errors: S!()
+            })
+            .is_retryable()
+        );
+        assert!(!AppError::TooManyRequests(60).is_retryable());
+    }
+
+    #[tokio::test]
+    async fn app_error_is_retryable_reqwest() {
+        // Nothing listens on port 0, so the connection is refused
+        let result = reqwest::get("http://127.0.0.1:0").await.unwrap_err();
+        let result = AppError::from(result);
+        assert!(result.is_retryable());
+        assert_ne!(result.detail(), "Reqwest Error");
+    }
+
+    #[tokio::test]
+    async fn app_error_detail_without_credentials() {
+        let result = reqwest::Client::new()
+            .post("http://127.0.0.1:0/1/messages.json?token=secret_token&user=secret_user")
+            .send()
+            .await
+            .unwrap_err();
+        let result = AppError::from(result).detail();
+        assert!(result.contains("http://127.0.0.1:0"));
+        assert!(!result.contains("secret"));
+    }
 }
@@ -0,0 +1,93 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    time::{Duration, SystemTime},
+};
+
+/// Capped exponential backoff, with jitter so that retries don't all land at the same moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub const fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+
+    /// The un-jittered delay for a given attempt, `base * 2^(attempt - 1)`, capped at `max`
+    pub fn ceiling(self, attempt: u32) -> Duration {
+        self.base
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max)
+    }
+
+    /// Half of the ceiling, plus a random amount up to the other half
+    pub fn delay(self, attempt: u32) -> Duration {
+        let ceiling = self.ceiling(attempt);
+        let half = ceiling / 2;
+        half + Self::random_upto(ceiling.saturating_sub(half))
+    }
+
+    /// A random amount anywhere up to the ceiling, spreads retries further apart than `delay`, at the cost of some being near instant
+    pub fn full_jitter(self, attempt: u32) -> Duration {
+        Self::random_upto(self.ceiling(attempt))
+    }
+
+    /// A random duration between zero and `limit`, inclusive, to the millisecond
+    fn random_upto(limit: Duration) -> Duration {
+        let limit_ms = u64::try_from(limit.as_millis()).unwrap_or(u64::MAX);
+        if limit_ms == 0 {
+            return Duration::ZERO;
+        }
+        let random = RandomState::new().hash_one(SystemTime::now());
+        Duration::from_millis(random % limit_ms.saturating_add(1))
+    }
+}
+
+/// backoff
+///
+/// cargo watch -q -c -w src/ -x 'test backoff -- --nocapture'
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKOFF: Backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(5));
+
+    #[test]
+    fn backoff_ceiling() {
+        assert_eq!(BACKOFF.ceiling(0), Duration::from_millis(500));
+        assert_eq!(BACKOFF.ceiling(1), Duration::from_millis(500));
+        assert_eq!(BACKOFF.ceiling(2), Duration::from_secs(1));
+        assert_eq!(BACKOFF.ceiling(3), Duration::from_secs(2));
+        assert_eq!(BACKOFF.ceiling(4), Duration::from_secs(4));
+        assert_eq!(BACKOFF.ceiling(5), Duration::from_secs(5));
+        assert_eq!(BACKOFF.ceiling(u32::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn backoff_delay() {
+        for attempt in 1..=10 {
+            let ceiling = BACKOFF.ceiling(attempt);
+            for _ in 0..20 {
+                let result = BACKOFF.delay(attempt);
+                assert!(result >= ceiling / 2);
+                assert!(result <= ceiling);
+            }
+        }
+        let zero = Backoff::new(Duration::ZERO, Duration::ZERO);
+        assert_eq!(zero.delay(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn backoff_full_jitter() {
+        for attempt in 1..=10 {
+            let ceiling = BACKOFF.ceiling(attempt);
+            for _ in 0..20 {
+                assert!(BACKOFF.full_jitter(attempt) <= ceiling);
+            }
+        }
+        let zero = Backoff::new(Duration::ZERO, Duration::ZERO);
+        assert_eq!(zero.full_jitter(3), Duration::ZERO);
+    }
+}
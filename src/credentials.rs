@@ -0,0 +1,344 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::watch;
+
+use crate::{
+    C,
+    app_env::{AppEnv, Recipient},
+    app_error::AppError,
+    db::{ModelRequest, ModelSetting},
+    notify::Channel,
+    request::{PostResponse, PushRequest},
+};
+
+/// Path of the user validation endpoint, relative to the configurable api base url
+const VALIDATE_PATH: &str = "1/users/validate.json";
+
+/// The most recent credential validation, `None` if Pushover isn't in the notify chain
+pub type CredentialSender = watch::Sender<Option<CredentialStatus>>;
+
+/// Only the devices are of interest, everything else is handled by `PostResponse`
+#[derive(Debug, Default, Deserialize)]
+struct ValidateBody {
+    #[serde(default)]
+    devices: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Validity {
+    Valid,
+    /// Pushover rejected the token, user, or a device
+    Invalid,
+    /// Pushover couldn't be reached, or returned a server error, so the credentials may or may not be valid
+    Unknown,
+}
+
+/// Validation result for a single recipient, the user key itself is never included
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct RecipientValidity {
+    pub recipient: String,
+    pub validity: Validity,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct CredentialStatus {
+    pub timestamp: u64,
+    pub recipients: Vec<RecipientValidity>,
+}
+
+impl CredentialStatus {
+    /// Validate the app token, and every recipient, against the Pushover api.
+    /// Returns `None` if Pushover isn't used, as there's nothing to validate
+    pub async fn validate(app_envs: &AppEnv) -> Option<Self> {
+        if !app_envs.notify_chain.contains(&Channel::Pushover) {
+            return None;
+        }
+        Some(Self::validate_all(app_envs).await)
+    }
+
+    /// Validate the credentials in use at startup, any stored at runtime take precedence over the env values.
+    /// With `PUSHOVER_VALIDATE_STRICT` set, credentials Pushover rejects are an error
+    pub async fn startup(app_envs: &AppEnv, sqlite: &SqlitePool) -> Result<Option<Self>, AppError> {
+        let credentials = Self::validate(&ModelSetting::apply(sqlite, app_envs).await?).await;
+        if app_envs.pushover_validate_strict
+            && let Some(credentials) = credentials.as_ref()
+        {
+            credentials.check()?;
+        }
+        Ok(credentials)
+    }
+
+    /// Validate every recipient, even if Pushover isn't currently in the notify chain
+    pub async fn validate_all(app_envs: &AppEnv) -> Self {
+        let mut recipients = vec![];
+        for recipient in &app_envs.recipients {
+            recipients.push(Self::validate_recipient(app_envs, recipient).await);
+        }
+        Self {
+            timestamp: ModelRequest::now(),
+            recipients,
+        }
+    }
+
+    /// A single validation request, a recipient restricted to devices is also checked against the devices Pushover knows about
+    async fn validate_recipient(app_envs: &AppEnv, recipient: &Recipient) -> RecipientValidity {
+        let (validity, error) = match Self::send_request(app_envs, recipient).await {
+            Ok(unknown) if unknown.is_empty() => (Validity::Valid, None),
+            Ok(unknown) => (
+                Validity::Invalid,
+                Some(format!("unknown devices: {}", unknown.join(","))),
+            ),
+            Err(e) if e.is_retryable() || e.is_rate_limited() => {
+                (Validity::Unknown, Some(e.detail()))
+            }
+            Err(e) => (Validity::Invalid, Some(e.detail())),
+        };
+        if let Some(error) = error.as_ref() {
+            tracing::warn!("{} credentials {validity:?}: {error}", recipient.name);
+        }
+        RecipientValidity {
+            recipient: C!(recipient.name),
+            validity,
+            error,
+        }
+    }
+
+    /// Returns any of the recipient's devices which Pushover doesn't know about
+    async fn send_request(
+        app_envs: &AppEnv,
+        recipient: &Recipient,
+    ) -> Result<Vec<String>, AppError> {
+        let url = reqwest::Url::parse_with_params(
+            &format!(
+                "{}/{VALIDATE_PATH}",
+                app_envs.pushover_url.trim_end_matches('/')
+            ),
+            [("token", &app_envs.token_app), ("user", &recipient.user)],
+        )?;
+        let response = PushRequest::get_client(app_envs)?.post(url).send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        PostResponse::from_parts(status, &headers, &body).check()?;
+        let devices = serde_json::from_slice::<ValidateBody>(&body)
+            .unwrap_or_default()
+            .devices;
+        Ok(recipient
+            .devices
+            .iter()
+            .filter(|i| !devices.contains(i))
+            .cloned()
+            .collect())
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        self.recipients
+            .iter()
+            .any(|i| i.validity == Validity::Invalid)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.recipients
+            .iter()
+            .all(|i| i.validity == Validity::Valid)
+    }
+
+    /// Every recipient which isn't valid, along with its error
+    fn errors(&self) -> String {
+        self.recipients
+            .iter()
+            .filter(|i| i.validity != Validity::Valid)
+            .map(|i| {
+                format!(
+                    "{}: {}",
+                    i.recipient,
+                    i.error.as_deref().unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Error if any recipient was rejected, unreachable isn't treated as invalid, else a Pi without network at boot could never start
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_invalid() {
+            return Err(AppError::CredentialsInvalid(self.errors()));
+        }
+        Ok(())
+    }
+
+    /// Error unless every recipient is definitely valid, used before storing new credentials
+    pub fn check_valid(&self) -> Result<(), AppError> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(AppError::CredentialsInvalid(self.errors()))
+        }
+    }
+}
+
+/// credentials
+///
+/// cargo watch -q -c -w src/ -x 'test credentials_ -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        S,
+        app_env::DEFAULT_RECIPIENT,
+        mock_server::{MockResponse, MockServer},
+        tests::{gen_app_envs, test_cleanup, test_setup},
+        ws_messages::CredentialsUpdate,
+    };
+
+    async fn gen_setup() -> (AppEnv, MockServer) {
+        let server = MockServer::start().await;
+        let mut app_envs = gen_app_envs(uuid::Uuid::new_v4());
+        app_envs.pushover_url = server.url();
+        (app_envs, server)
+    }
+
+    #[tokio::test]
+    async fn credentials_validate_valid() {
+        let (mut app_envs, server) = gen_setup().await;
+        app_envs.recipients[0].devices = vec![S!("iphone")];
+        server.push(MockResponse::new(
+            200,
+            r#"{"status":1,"group":0,"devices":["iphone","nexus5"],"request":"request"}"#,
+        ));
+
+        let result = CredentialStatus::validate(&app_envs).await.unwrap();
+        assert_eq!(
+            result.recipients,
+            [RecipientValidity {
+                recipient: S!(DEFAULT_RECIPIENT),
+                validity: Validity::Valid,
+                error: None
+            }]
+        );
+        assert!(result.check().is_ok());
+        assert!(result.check_valid().is_ok());
+
+        let received = server.requests();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].path.starts_with("/1/users/validate.json?"));
+        assert!(received[0].path.contains("token=test_token_app"));
+        assert!(received[0].path.contains("user=test_token_user"));
+
+        // Never serialized with the user key
+        let result = serde_json::to_string(&result).unwrap();
+        assert!(!result.contains("test_token_user"));
+    }
+
+    #[tokio::test]
+    async fn credentials_validate_invalid() {
+        let (app_envs, server) = gen_setup().await;
+        server.push(MockResponse::new(
+            400,
+            r#"{"user":"invalid","errors":["user key is invalid"],"status":0,"request":"request"}"#,
+        ));
+
+        let result = CredentialStatus::validate(&app_envs).await.unwrap();
+        assert_eq!(result.recipients[0].validity, Validity::Invalid);
+        assert_eq!(
+            result.recipients[0].error,
+            Some(S!("Pushover: invalid user: user key is invalid"))
+        );
+        assert_eq!(
+            result.check().unwrap_err().to_string(),
+            "Invalid Pushover credentials: default: Pushover: invalid user: user key is invalid"
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_validate_unknown_device() {
+        let (mut app_envs, server) = gen_setup().await;
+        app_envs.recipients[0].devices = vec![S!("iphone"), S!("pixel")];
+        server.push(MockResponse::new(
+            200,
+            r#"{"status":1,"group":0,"devices":["iphone"],"request":"request"}"#,
+        ));
+
+        let result = CredentialStatus::validate(&app_envs).await.unwrap();
+        assert_eq!(result.recipients[0].validity, Validity::Invalid);
+        assert_eq!(
+            result.recipients[0].error,
+            Some(S!("unknown devices: pixel"))
+        );
+        assert!(result.check().is_err());
+    }
+
+    #[tokio::test]
+    // Unreachable, or a server error, isn't treated as invalid
+    async fn credentials_validate_unknown() {
+        let (mut app_envs, server) = gen_setup().await;
+        server.push(MockResponse::new(503, ""));
+
+        let result = CredentialStatus::validate(&app_envs).await.unwrap();
+        assert_eq!(result.recipients[0].validity, Validity::Unknown);
+        assert!(result.check().is_ok());
+        // Can't be used to store new credentials, as they may be invalid
+        assert!(result.check_valid().is_err());
+
+        app_envs.pushover_url = S!("http://127.0.0.1:0");
+        let result = CredentialStatus::validate(&app_envs).await.unwrap();
+        assert_eq!(result.recipients[0].validity, Validity::Unknown);
+        assert!(result.recipients[0].error.is_some());
+        assert!(result.check().is_ok());
+    }
+
+    #[tokio::test]
+    // Nothing to validate if Pushover isn't used
+    async fn credentials_validate_not_used() {
+        let (mut app_envs, server) = gen_setup().await;
+        app_envs.notify_chain = vec![Channel::Telegram];
+
+        assert!(CredentialStatus::validate(&app_envs).await.is_none());
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    // Credentials stored at runtime are validated at startup, rather than the env values they replace
+    async fn credentials_startup_stored() {
+        let server = MockServer::start().await;
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.pushover_url = server.url();
+        app_envs.pushover_validate_strict = true;
+
+        // The env credentials are rejected
+        server.push(MockResponse::new(
+            400,
+            r#"{"user":"invalid","errors":["user key is invalid"],"status":0,"request":"request"}"#,
+        ));
+        let result = CredentialStatus::startup(&app_envs, &sqlite).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid Pushover credentials: default: Pushover: invalid user: user key is invalid"
+        );
+
+        ModelSetting::set_credentials(
+            &sqlite,
+            &CredentialsUpdate {
+                token_app: Some(S!("stored_token")),
+                users: [(S!(DEFAULT_RECIPIENT), S!("stored_user"))].into(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let result = CredentialStatus::startup(&app_envs, &sqlite)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.is_valid());
+        let received = server.requests();
+        assert_eq!(received.len(), 2);
+        assert!(received[1].path.contains("token=stored_token"));
+        assert!(received[1].path.contains("user=stored_user"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
@@ -0,0 +1,3 @@
+ALTER TABLE alarm ADD COLUMN recipients TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE request ADD COLUMN recipient TEXT NOT NULL DEFAULT 'default';
@@ -0,0 +1,3 @@
+ALTER TABLE request ADD COLUMN channel TEXT NOT NULL DEFAULT 'pushover';
+
+ALTER TABLE request ADD COLUMN delivered INTEGER NOT NULL DEFAULT 0 CHECK (delivered IN (0, 1));
@@ -0,0 +1,15 @@
+ALTER TABLE alarm ADD COLUMN title TEXT;
+
+ALTER TABLE alarm ADD COLUMN sound TEXT;
+
+ALTER TABLE alarm ADD COLUMN url TEXT;
+
+ALTER TABLE alarm ADD COLUMN url_title TEXT;
+
+ALTER TABLE alarm ADD COLUMN html INTEGER NOT NULL DEFAULT 0 CHECK (html IN (0, 1));
+
+ALTER TABLE alarm ADD COLUMN monospace INTEGER NOT NULL DEFAULT 0 CHECK (monospace IN (0, 1));
+
+ALTER TABLE alarm ADD COLUMN ttl INTEGER CHECK (ttl > 0);
+
+ALTER TABLE alarm ADD COLUMN timestamp INTEGER CHECK (timestamp > 0);
@@ -0,0 +1,11 @@
+ALTER TABLE request ADD COLUMN http_status INTEGER;
+
+ALTER TABLE request ADD COLUMN api_status INTEGER;
+
+ALTER TABLE request ADD COLUMN provider_request_id TEXT;
+
+ALTER TABLE request ADD COLUMN errors TEXT;
+
+ALTER TABLE request ADD COLUMN limit_remaining INTEGER;
+
+ALTER TABLE request ADD COLUMN limit_reset INTEGER;
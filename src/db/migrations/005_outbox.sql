@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS outbox (
+	outbox_id INTEGER PRIMARY KEY AUTOINCREMENT,
+	timestamp INTEGER NOT NULL,
+	push_index INTEGER CHECK (push_index >= 0),
+	recipient TEXT NOT NULL,
+	message TEXT NOT NULL,
+	options TEXT NOT NULL,
+	attempts INTEGER NOT NULL DEFAULT 0,
+	last_error TEXT
+) STRICT;
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS request_limit ON request (channel, recipient, is_alarm, timestamp);
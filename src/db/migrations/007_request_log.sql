@@ -0,0 +1,17 @@
+ALTER TABLE request ADD COLUMN kind TEXT GENERATED ALWAYS AS (CASE WHEN is_alarm THEN 'alarm' ELSE 'test_request' END) VIRTUAL;
+
+ALTER TABLE request ADD COLUMN alarm_id INTEGER;
+
+ALTER TABLE request ADD COLUMN push_index INTEGER CHECK (push_index >= 0);
+
+ALTER TABLE request ADD COLUMN message TEXT;
+
+ALTER TABLE request ADD COLUMN latency_ms INTEGER CHECK (latency_ms >= 0);
+
+CREATE INDEX IF NOT EXISTS request_timestamp ON request (timestamp);
+
+ALTER TABLE outbox ADD COLUMN alarm_id INTEGER;
+
+UPDATE outbox SET alarm_id = (SELECT alarm_id FROM alarm LIMIT 1) WHERE push_index IS NOT NULL;
+
+DELETE FROM outbox WHERE push_index IS NOT NULL AND alarm_id IS NULL;
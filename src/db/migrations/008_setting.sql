@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS setting (
+	setting_id INTEGER PRIMARY KEY AUTOINCREMENT,
+	name TEXT NOT NULL UNIQUE,
+	value TEXT NOT NULL,
+	timestamp INTEGER NOT NULL
+) STRICT;
@@ -1,13 +1,17 @@
 mod model_alarm;
 mod model_oblique_strategy;
+mod model_outbox;
 mod model_request;
+mod model_setting;
 mod model_timezone;
 
 use std::fs;
 
 pub use model_alarm::ModelAlarm;
 pub use model_oblique_strategy::ModelObliqueStrategy;
-pub use model_request::ModelRequest;
+pub use model_outbox::ModelOutbox;
+pub use model_request::{ModelRequest, RequestCount};
+pub use model_setting::ModelSetting;
 pub use model_timezone::ModelTimezone;
 
 use sqlx::{ConnectOptions, SqlitePool, sqlite::SqliteJournalMode};
@@ -15,6 +19,18 @@ use tracing::error;
 
 use crate::app_env::AppEnv;
 
+/// Schema changes made after the initial `init_db.sql`, applied in order, tracked via `PRAGMA user_version`
+const MIGRATIONS: [&str; 8] = [
+    include_str!("migrations/001_recipients.sql"),
+    include_str!("migrations/002_channel.sql"),
+    include_str!("migrations/003_push_options.sql"),
+    include_str!("migrations/004_pushover_response.sql"),
+    include_str!("migrations/005_outbox.sql"),
+    include_str!("migrations/006_request_limit_index.sql"),
+    include_str!("migrations/007_request_log.sql"),
+    include_str!("migrations/008_setting.sql"),
+];
+
 /// If file doesn't exist on disk, create
 /// Probably can be removed, as sqlx has a setting to create file if not found
 fn file_exists(filename: &str) {
@@ -89,11 +105,74 @@ async fn create_tables(sqlite: &SqlitePool) {
     }
 }
 
+/// Apply any migrations that haven't yet been run, each one in its own transaction
+async fn run_migrations(sqlite: &SqlitePool) -> Result<(), sqlx::Error> {
+    let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+        .fetch_one(sqlite)
+        .await?;
+    let applied = usize::try_from(version).unwrap_or_default();
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let mut transaction = sqlite.begin().await?;
+        sqlx::query(migration).execute(&mut *transaction).await?;
+        sqlx::query(&format!("PRAGMA user_version = {}", index + 1))
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        tracing::debug!("migration {} applied", index + 1);
+    }
+    Ok(())
+}
+
+/// Size of the database file, as reported by sqlite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbSize {
+    pub page_count: i64,
+    pub freelist_count: i64,
+    pub page_size: i64,
+}
+
+impl DbSize {
+    pub async fn get(sqlite: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let (page_count,) = sqlx::query_as::<_, (i64,)>("PRAGMA page_count")
+            .fetch_one(sqlite)
+            .await?;
+        let (freelist_count,) = sqlx::query_as::<_, (i64,)>("PRAGMA freelist_count")
+            .fetch_one(sqlite)
+            .await?;
+        let (page_size,) = sqlx::query_as::<_, (i64,)>("PRAGMA page_size")
+            .fetch_one(sqlite)
+            .await?;
+        Ok(Self {
+            page_count,
+            freelist_count,
+            page_size,
+        })
+    }
+
+    pub const fn bytes(self) -> i64 {
+        self.page_count * self.page_size
+    }
+}
+
+/// Rebuild the database file, returning the size before and after
+pub async fn vacuum(sqlite: &SqlitePool) -> Result<(DbSize, DbSize), sqlx::Error> {
+    let before = DbSize::get(sqlite).await?;
+    sqlx::query("VACUUM").execute(sqlite).await?;
+    Ok((before, DbSize::get(sqlite).await?))
+}
+
+/// Let sqlite update its query planner statistics
+pub async fn optimize(sqlite: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("PRAGMA optimize").execute(sqlite).await?;
+    Ok(())
+}
+
 /// Init db connection, works if folder/files exists or not
 pub async fn init_db(app_envs: &AppEnv) -> Result<SqlitePool, sqlx::Error> {
     file_exists(&app_envs.location_sqlite);
     let sqlite = get_db(app_envs).await?;
     create_tables(&sqlite).await;
+    run_migrations(&sqlite).await?;
     insert_env_timezone(&sqlite, app_envs).await;
     Ok(sqlite)
 }
@@ -142,6 +221,47 @@ mod tests {
         test_cleanup(uuid, None).await;
     }
 
+    #[tokio::test]
+    async fn sql_mod_vacuum() {
+        let (_, sqlite, uuid) = crate::tests::test_setup().await;
+        for _ in 0..500 {
+            sqlx::query("INSERT INTO request(timestamp, is_alarm, message) VALUES (1, true, $1)")
+                .bind("a".repeat(100))
+                .execute(&sqlite)
+                .await
+                .unwrap();
+        }
+        sqlx::query("DELETE FROM request")
+            .execute(&sqlite)
+            .await
+            .unwrap();
+
+        let (before, after) = vacuum(&sqlite).await.unwrap();
+        assert!(before.freelist_count > 0);
+        assert_eq!(after.freelist_count, 0);
+        assert!(after.bytes() < before.bytes());
+        assert!(optimize(&sqlite).await.is_ok());
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn sql_mod_migrations_applied() {
+        let (_, sqlite, uuid) = crate::tests::test_setup().await;
+
+        let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+            .fetch_one(&sqlite)
+            .await
+            .unwrap();
+        assert_eq!(version, i64::try_from(MIGRATIONS.len()).unwrap());
+
+        // Running again is a no-op
+        let result = run_migrations(&sqlite).await;
+        assert!(result.is_ok());
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
     #[tokio::test]
     async fn sql_mod_db_created_with_timezone() {
         let uuid = uuid::Uuid::new_v4();
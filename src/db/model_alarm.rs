@@ -1,27 +1,69 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::fmt;
 
-use crate::{app_error::AppError, ws_messages::HourMinuteMsg};
+use crate::{
+    app_error::AppError,
+    ws_messages::{HourMinuteMsg, PushOptions},
+};
+
+/// Names of the recipients an alarm targets, stored as a comma separated string, an empty list means every recipient
+#[derive(
+    Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct RecipientNames(pub Vec<String>);
+
+impl From<String> for RecipientNames {
+    fn from(value: String) -> Self {
+        Self(
+            value
+                .split(',')
+                .filter(|i| !i.is_empty())
+                .map(std::borrow::ToOwned::to_owned)
+                .collect(),
+        )
+    }
+}
+
+impl From<Option<Vec<String>>> for RecipientNames {
+    fn from(value: Option<Vec<String>>) -> Self {
+        Self(value.unwrap_or_default())
+    }
+}
+
+impl fmt::Display for RecipientNames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+#[derive(
+    sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
 pub struct ModelAlarm {
     #[serde(skip_serializing)]
     pub alarm_id: i64,
     pub hour: i8,
     pub minute: i8,
     pub message: Option<String>,
+    #[sqlx(try_from = "String")]
+    pub recipients: RecipientNames,
+    #[sqlx(flatten)]
+    pub options: PushOptions,
 }
 
 impl fmt::Display for ModelAlarm {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "alarm_id: {}, hour:{}, minute:{}, message: {}",
+            "alarm_id: {}, hour:{}, minute:{}, message: {}, recipients: {}",
             self.alarm_id,
             self.hour,
             self.minute,
-            self.message.as_ref().unwrap_or(&String::new())
+            self.message.as_ref().unwrap_or(&String::new()),
+            self.recipients
         )
     }
 }
@@ -33,7 +75,8 @@ impl ModelAlarm {
     CASE
         WHEN message = '' THEN NULL
         ELSE message
-    END AS message
+    END AS message,
+    recipients, title, sound, url, url_title, html, monospace, ttl, timestamp
 FROM
     alarm";
         Ok(sqlx::query_as::<_, Self>(sql)
@@ -42,22 +85,40 @@ FROM
     }
 
     pub async fn add(sqlite: &SqlitePool, data: HourMinuteMsg) -> Result<(), AppError> {
-        let sql = "INSERT INTO alarm(hour, minute, message) VALUES ($1, $2, $3) RETURNING alarm_id, hour, minute, message";
+        let sql = "INSERT INTO alarm(hour, minute, message, recipients, title, sound, url, url_title, html, monospace, ttl, timestamp) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING *";
         sqlx::query_as::<_, Self>(sql)
             .bind(data.hour)
             .bind(data.minute)
             .bind(data.message)
+            .bind(RecipientNames::from(data.recipients).to_string())
+            .bind(data.options.title)
+            .bind(data.options.sound)
+            .bind(data.options.url)
+            .bind(data.options.url_title)
+            .bind(data.options.html)
+            .bind(data.options.monospace)
+            .bind(data.options.ttl)
+            .bind(data.options.timestamp)
             .fetch_one(sqlite)
             .await?;
         Ok(())
     }
 
     pub async fn update(sqlite: &SqlitePool, data: HourMinuteMsg) -> Result<(), AppError> {
-        let sql = "UPDATE alarm SET hour = $1, minute = $2, message = $3 RETURNING alarm_id, hour, minute, message;";
+        let sql = "UPDATE alarm SET hour = $1, minute = $2, message = $3, recipients = $4, title = $5, sound = $6, url = $7, url_title = $8, html = $9, monospace = $10, ttl = $11, timestamp = $12 RETURNING *;";
         sqlx::query_as::<_, Self>(sql)
             .bind(data.hour)
             .bind(data.minute)
             .bind(data.message)
+            .bind(RecipientNames::from(data.recipients).to_string())
+            .bind(data.options.title)
+            .bind(data.options.sound)
+            .bind(data.options.url)
+            .bind(data.options.url_title)
+            .bind(data.options.html)
+            .bind(data.options.monospace)
+            .bind(data.options.ttl)
+            .bind(data.options.timestamp)
             .fetch_one(sqlite)
             .await?;
         Ok(())
@@ -140,6 +201,58 @@ mod tests {
         test_cleanup(uuid, Some(sqlite)).await;
     }
 
+    #[tokio::test]
+    async fn model_alarm_add_ok_recipients() {
+        let (_, sqlite, uuid) = test_setup().await;
+        let mut data = HourMinuteMsg::from((10, 10, None));
+        data.recipients = Some(vec!["jack".to_owned(), "sam".to_owned()]);
+
+        let result = ModelAlarm::add(&sqlite, data).await;
+        assert!(result.is_ok());
+
+        let result = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert_eq!(
+            result.recipients,
+            RecipientNames(vec!["jack".to_owned(), "sam".to_owned()])
+        );
+
+        let data = HourMinuteMsg::from((10, 10, None));
+        ModelAlarm::update(&sqlite, data).await.unwrap();
+
+        let result = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert!(result.recipients.0.is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_alarm_add_ok_options() {
+        let (_, sqlite, uuid) = test_setup().await;
+        let mut data = HourMinuteMsg::from((10, 10, None));
+        let options = PushOptions {
+            title: Some("title".to_owned()),
+            sound: Some("siren".to_owned()),
+            url: Some("https://www.example.com".to_owned()),
+            url_title: Some("example".to_owned()),
+            html: false,
+            monospace: true,
+            ttl: Some(3600),
+            timestamp: Some(1_700_000_000),
+        };
+        data.options = options.clone();
+
+        ModelAlarm::add(&sqlite, data).await.unwrap();
+
+        let result = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert_eq!(result.options, options);
+
+        let data = HourMinuteMsg::from((10, 10, None));
+        ModelAlarm::update(&sqlite, data).await.unwrap();
+
+        let result = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert_eq!(result.options, PushOptions::default());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
     #[tokio::test]
     async fn model_alarm_second_add_err() {
         let (_, sqlite, uuid) = test_setup().await;
@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::fmt;
+
+use crate::{
+    app_error::AppError, db::ModelRequest, request::PushRequest, ws_messages::PushOptions,
+};
+
+/// A push to a single recipient, written before the first attempt is made, and removed once it's been settled
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ModelOutbox {
+    pub outbox_id: i64,
+    #[sqlx(try_from = "i64")]
+    pub timestamp: u64,
+    pub push_index: Option<i64>,
+    pub recipient: String,
+    pub message: String,
+    pub options: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub alarm_id: Option<i64>,
+}
+
+impl fmt::Display for ModelOutbox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "outbox_id: {}, timestamp: {}, push_index: {:?}, recipient: {}, attempts: {}",
+            self.outbox_id, self.timestamp, self.push_index, self.recipient, self.attempts
+        )
+    }
+}
+
+impl ModelOutbox {
+    /// Convert the stored alarm_id and push_index back into a PushRequest, a null index is a test request
+    pub fn push_request(&self) -> PushRequest {
+        match (self.alarm_id, self.push_index) {
+            (Some(alarm_id), Some(index)) => PushRequest::Alarm {
+                alarm_id,
+                push_index: u8::try_from(index).unwrap_or_default(),
+            },
+            _ => PushRequest::TestRequest,
+        }
+    }
+
+    /// Options are stored as json
+    pub fn push_options(&self) -> PushOptions {
+        serde_json::from_str(&self.options).unwrap_or_default()
+    }
+
+    pub async fn insert(
+        sqlite: &SqlitePool,
+        push_request: &PushRequest,
+        recipient: &str,
+        message: &str,
+        options: &PushOptions,
+    ) -> Result<Self, AppError> {
+        let sql = "INSERT INTO outbox(timestamp, alarm_id, push_index, recipient, message, options) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *";
+        let query = sqlx::query_as::<_, Self>(sql)
+            .bind(ModelRequest::now_i64())
+            .bind(push_request.alarm_id())
+            .bind(push_request.push_index().map(i64::from))
+            .bind(recipient)
+            .bind(message)
+            .bind(serde_json::to_string(options).unwrap_or_default())
+            .fetch_one(sqlite)
+            .await?;
+        Ok(query)
+    }
+
+    /// Every entry younger than `max_age` seconds, oldest first, whether or not an attempt has been recorded.
+    /// An entry that hasn't failed yet, and is younger than `in_flight` seconds, could still be being sent, so is skipped.
+    /// Any older is left over from a crash or a restart
+    pub async fn get_pending(
+        sqlite: &SqlitePool,
+        max_age: u64,
+        in_flight: u64,
+    ) -> Result<Vec<Self>, AppError> {
+        let sql = "SELECT * FROM outbox WHERE timestamp >= $1 AND (attempts > 0 OR timestamp <= $2) ORDER BY outbox_id";
+        let now = ModelRequest::now_i64();
+        Ok(sqlx::query_as::<_, Self>(sql)
+            .bind(now - i64::try_from(max_age).unwrap_or(i64::MAX))
+            .bind(now - i64::try_from(in_flight).unwrap_or(i64::MAX))
+            .fetch_all(sqlite)
+            .await?)
+    }
+
+    /// Record a failed attempt, the entry is left for the outbox worker to retry
+    pub async fn set_failed(
+        sqlite: &SqlitePool,
+        outbox_id: i64,
+        error: &str,
+    ) -> Result<(), AppError> {
+        let sql = "UPDATE outbox SET attempts = attempts + 1, last_error = $1 WHERE outbox_id = $2";
+        sqlx::query(sql)
+            .bind(error)
+            .bind(outbox_id)
+            .execute(sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove an entry if it was delivered, or can never be delivered, else record the failure.
+    /// Returns true if the entry was left pending
+    pub async fn settle(
+        sqlite: &SqlitePool,
+        outbox_id: i64,
+        result: &Result<(), AppError>,
+    ) -> Result<bool, AppError> {
+        match result {
+            Err(e) if e.is_retryable() => {
+                Self::set_failed(sqlite, outbox_id, &e.detail()).await?;
+                Ok(true)
+            }
+            _ => {
+                Self::delete(sqlite, outbox_id).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    pub async fn delete(sqlite: &SqlitePool, outbox_id: i64) -> Result<(), AppError> {
+        let sql = "DELETE FROM outbox WHERE outbox_id = $1";
+        sqlx::query(sql).bind(outbox_id).execute(sqlite).await?;
+        Ok(())
+    }
+
+    /// Remove every entry older than `max_age` seconds, returns the number of entries removed
+    pub async fn delete_expired(sqlite: &SqlitePool, max_age: u64) -> Result<u64, AppError> {
+        let sql = "DELETE FROM outbox WHERE timestamp < $1";
+        let result = sqlx::query(sql)
+            .bind(ModelRequest::now_i64() - i64::try_from(max_age).unwrap_or(i64::MAX))
+            .execute(sqlite)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Once an alarm has been dismissed, none of its pushes should be delivered
+    pub async fn delete_alarms(sqlite: &SqlitePool) -> Result<(), AppError> {
+        let sql = "DELETE FROM outbox WHERE push_index IS NOT NULL";
+        sqlx::query(sql).execute(sqlite).await?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub async fn test_get_all(sqlite: &SqlitePool) -> Result<Vec<Self>, AppError> {
+        let sql = "SELECT * FROM outbox";
+        Ok(sqlx::query_as::<_, Self>(sql).fetch_all(sqlite).await?)
+    }
+}
+
+/// model_outbox
+///
+/// cargo watch -q -c -w src/ -x 'test model_outbox -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        S,
+        app_env::DEFAULT_RECIPIENT,
+        app_error::PushoverError,
+        tests::{test_cleanup, test_setup},
+    };
+
+    #[tokio::test]
+    async fn model_outbox_insert() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        let options = PushOptions {
+            title: Some(S!("title")),
+            ttl: Some(60),
+            ..PushOptions::default()
+        };
+
+        let result = ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::test_alarm(4),
+            DEFAULT_RECIPIENT,
+            "message",
+            &options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.outbox_id, 1);
+        assert_eq!(result.timestamp, ModelRequest::now());
+        assert_eq!(result.alarm_id, Some(1));
+        assert_eq!(result.push_index, Some(4));
+        assert!(matches!(
+            result.push_request(),
+            PushRequest::Alarm {
+                alarm_id: 1,
+                push_index: 4
+            }
+        ));
+        assert_eq!(result.push_options(), options);
+        assert_eq!(result.attempts, 0);
+
+        let result = ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::TestRequest,
+            DEFAULT_RECIPIENT,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert!(result.push_index.is_none());
+        assert!(matches!(result.push_request(), PushRequest::TestRequest));
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Only retryable failures are left in the outbox, and only entries that have failed are pending
+    async fn model_outbox_settle() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        let mut ids = vec![];
+        for _ in 0..3 {
+            let entry = ModelOutbox::insert(
+                &sqlite,
+                &PushRequest::test_alarm(1),
+                DEFAULT_RECIPIENT,
+                "message",
+                &PushOptions::default(),
+            )
+            .await
+            .unwrap();
+            ids.push(entry.outbox_id);
+        }
+        assert!(
+            ModelOutbox::get_pending(&sqlite, 300, 60)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(!ModelOutbox::settle(&sqlite, ids[0], &Ok(())).await.unwrap());
+        assert!(
+            !ModelOutbox::settle(
+                &sqlite,
+                ids[1],
+                &Err(AppError::Pushover(PushoverError::InvalidUser(S!()))),
+            )
+            .await
+            .unwrap()
+        );
+        assert!(
+            ModelOutbox::settle(
+                &sqlite,
+                ids[2],
+                &Err(AppError::Pushover(PushoverError::Server(503))),
+            )
+            .await
+            .unwrap()
+        );
+
+        let result = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 1);
+        let result = ModelOutbox::get_pending(&sqlite, 300, 60).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].outbox_id, ids[2]);
+        assert_eq!(result[0].attempts, 1);
+        assert_eq!(
+            result[0].last_error.as_deref(),
+            Some("Pushover: server error, status 503")
+        );
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Entries that were never attempted are pending once they can't still be in flight, but never once expired
+    async fn model_outbox_get_pending() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for (index, age) in [(1, 0), (2, 120), (3, 600)] {
+            let entry = ModelOutbox::insert(
+                &sqlite,
+                &PushRequest::test_alarm(index),
+                DEFAULT_RECIPIENT,
+                "message",
+                &PushOptions::default(),
+            )
+            .await
+            .unwrap();
+            sqlx::query("UPDATE outbox SET timestamp = timestamp - $1 WHERE outbox_id = $2")
+                .bind(age)
+                .bind(entry.outbox_id)
+                .execute(&sqlite)
+                .await
+                .unwrap();
+        }
+
+        let result = ModelOutbox::get_pending(&sqlite, 300, 60).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].push_index, Some(2));
+        assert_eq!(result[0].attempts, 0);
+
+        let result = ModelOutbox::get_pending(&sqlite, 300, 0).await.unwrap();
+        assert_eq!(result.len(), 2);
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_outbox_delete_expired() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::test_alarm(1),
+            DEFAULT_RECIPIENT,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+        sqlx::query("UPDATE outbox SET timestamp = 0")
+            .execute(&sqlite)
+            .await
+            .unwrap();
+        ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::test_alarm(2),
+            DEFAULT_RECIPIENT,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let result = ModelOutbox::delete_expired(&sqlite, 300).await.unwrap();
+        assert_eq!(result, 1);
+        let result = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].push_index, Some(2));
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_outbox_delete_alarms() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for push_request in [PushRequest::test_alarm(1), PushRequest::TestRequest] {
+            ModelOutbox::insert(
+                &sqlite,
+                &push_request,
+                DEFAULT_RECIPIENT,
+                "message",
+                &PushOptions::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        ModelOutbox::delete_alarms(&sqlite).await.unwrap();
+        let result = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result[0].push_index.is_none());
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
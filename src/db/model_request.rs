@@ -1,30 +1,71 @@
 use jiff::{SpanRound, ToSpan, Unit};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::{fmt, time::SystemTime};
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
 
 use crate::app_error::AppError;
-use crate::request::PushRequest;
+use crate::notify::Channel;
+use crate::request::{PostResponse, PushRequest, RequestKind};
+use crate::ws_messages::RequestLogFilter;
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+/// Only the start of each message is stored, it's just to help identify the request
+const MAX_MESSAGE_LEN: usize = 100;
+
+#[derive(
+    sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
 pub struct ModelRequest {
     pub request_id: i64,
     #[sqlx(try_from = "i64")]
     pub timestamp: u64,
     pub is_alarm: bool,
+    pub recipient: String,
+    #[sqlx(try_from = "String")]
+    pub channel: Channel,
+    pub delivered: bool,
+    pub http_status: Option<i64>,
+    pub api_status: Option<i64>,
+    pub provider_request_id: Option<String>,
+    pub errors: Option<String>,
+    pub limit_remaining: Option<i64>,
+    pub limit_reset: Option<i64>,
+    #[sqlx(try_from = "String")]
+    pub kind: RequestKind,
+    pub alarm_id: Option<i64>,
+    pub push_index: Option<i64>,
+    pub message: Option<String>,
+    pub latency_ms: Option<i64>,
 }
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Count {
-    pub count: i64,
+/// Number of requests made in the past hour, and the past day
+#[derive(
+    sqlx::FromRow,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+pub struct RequestCount {
+    pub hour: i64,
+    pub day: i64,
 }
 
 impl fmt::Display for ModelRequest {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "request_id: {}, timestamp:{}",
-            self.request_id, self.timestamp,
+            "request_id: {}, timestamp:{}, recipient: {}, channel: {}, delivered: {}",
+            self.request_id, self.timestamp, self.recipient, self.channel, self.delivered
         )
     }
 }
@@ -43,53 +84,169 @@ impl ModelRequest {
         i64::try_from(Self::now()).unwrap_or_default()
     }
 
-    const fn count_query<'a>(push_request: &PushRequest) -> &'a str {
-        match push_request {
-            PushRequest::Alarm(_) => {
-                "SELECT COUNT(*) AS count FROM request WHERE is_alarm = TRUE AND timestamp BETWEEN $1 AND $2 ORDER BY timestamp"
-            }
-            PushRequest::TestRequest => {
-                "SELECT COUNT(*) AS count FROM request WHERE is_alarm = FALSE AND timestamp BETWEEN $1 AND $2 ORDER BY timestamp"
-            }
-        }
-    }
-
     /// Is the given PushRequest Alarm - used in ModelRequest query
     const fn is_alarm(push_request: &PushRequest) -> bool {
         match push_request {
-            PushRequest::Alarm(_) => true,
+            PushRequest::Alarm { .. } => true,
             PushRequest::TestRequest => false,
         }
     }
 
-    /// Count the number of request made in the past hour, based on type of request
-    pub async fn count_past_hour(
+    /// Window length in seconds
+    fn seconds(span: jiff::Span) -> i64 {
+        span.round(SpanRound::new().largest(Unit::Second).days_are_24_hours())
+            .map_or(0, |i| i.get_seconds())
+    }
+
+    /// Count the number of request made in the past hour, and past day, via a channel to a given recipient, based on type of request.
+    /// Only the rows in the past day are scanned, via the `request_limit` index
+    pub async fn count_recent(
         sqlite: &SqlitePool,
         push_request: &PushRequest,
-    ) -> Result<i64, AppError> {
-        let one_hour = 1
-            .hour()
-            .round(SpanRound::new().largest(Unit::Second))
-            .map_or(0, |i| i.get_seconds());
-        let result = sqlx::query_as::<_, Count>(Self::count_query(push_request))
-            .bind(Self::now_i64() - one_hour)
-            .bind(Self::now_i64())
+        channel: Channel,
+        recipient: &str,
+    ) -> Result<RequestCount, AppError> {
+        let now = Self::now_i64();
+        let sql = "SELECT COUNT(*) AS day, COALESCE(SUM(timestamp >= $1), 0) AS hour FROM request WHERE channel = $2 AND recipient = $3 AND is_alarm = $4 AND timestamp BETWEEN $5 AND $6";
+        let result = sqlx::query_as::<_, RequestCount>(sql)
+            .bind(now - Self::seconds(1.hour()))
+            .bind(channel.as_str())
+            .bind(recipient)
+            .bind(Self::is_alarm(push_request))
+            .bind(now - Self::seconds(1.day()))
+            .bind(now)
             .fetch_one(sqlite)
             .await?;
-        Ok(result.count)
+        Ok(result)
     }
 
-    // insert a new request with timestamp
-    pub async fn insert(sqlite: &SqlitePool, push_request: &PushRequest) -> Result<Self, AppError> {
-        let sql = "INSERT INTO request(timestamp, is_alarm) VALUES ($1, $2) RETURNING request_id, is_alarm, timestamp";
+    // insert a new request with timestamp, it isn't marked as delivered until the channel has confirmed the send
+    // Alarm requests are linked to the alarm that was ringing, even if it has since been changed or deleted
+    pub async fn insert(
+        sqlite: &SqlitePool,
+        push_request: &PushRequest,
+        channel: Channel,
+        recipient: &str,
+        message: &str,
+    ) -> Result<Self, AppError> {
+        let sql = "INSERT INTO request(timestamp, is_alarm, alarm_id, push_index, recipient, channel, message) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *";
         let query = sqlx::query_as::<_, Self>(sql)
             .bind(Self::now_i64())
             .bind(Self::is_alarm(push_request))
+            .bind(push_request.alarm_id())
+            .bind(push_request.push_index())
+            .bind(recipient)
+            .bind(channel.as_str())
+            .bind(message.chars().take(MAX_MESSAGE_LEN).collect::<String>())
             .fetch_one(sqlite)
             .await?;
         Ok(query)
     }
 
+    fn latency_ms(latency: Duration) -> i64 {
+        i64::try_from(latency.as_millis()).unwrap_or(i64::MAX)
+    }
+
+    /// Mark a request as delivered
+    pub async fn set_delivered(
+        sqlite: &SqlitePool,
+        request_id: i64,
+        latency: Duration,
+    ) -> Result<(), AppError> {
+        let sql = "UPDATE request SET delivered = TRUE, latency_ms = $1 WHERE request_id = $2";
+        sqlx::query(sql)
+            .bind(Self::latency_ms(latency))
+            .bind(request_id)
+            .execute(sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Store the provider response against a request, marking it delivered if it was successful
+    pub async fn set_response(
+        sqlite: &SqlitePool,
+        request_id: i64,
+        response: &PostResponse,
+        latency: Duration,
+    ) -> Result<(), AppError> {
+        let sql = "UPDATE request SET delivered = $1, http_status = $2, api_status = $3, provider_request_id = $4, errors = $5, limit_remaining = $6, limit_reset = $7, latency_ms = $8 WHERE request_id = $9";
+        sqlx::query(sql)
+            .bind(response.is_delivered())
+            .bind(response.http_status)
+            .bind(response.status)
+            .bind(&response.request)
+            .bind(response.errors_joined())
+            .bind(response.limit_remaining)
+            .bind(response.limit_reset)
+            .bind(Self::latency_ms(latency))
+            .bind(request_id)
+            .execute(sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Store an error against a request, where no response was received
+    pub async fn set_error(
+        sqlite: &SqlitePool,
+        request_id: i64,
+        error: &str,
+        latency: Duration,
+    ) -> Result<(), AppError> {
+        let sql = "UPDATE request SET errors = $1, latency_ms = $2 WHERE request_id = $3";
+        sqlx::query(sql)
+            .bind(error)
+            .bind(Self::latency_ms(latency))
+            .bind(request_id)
+            .execute(sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Most recent requests first, every filter is optional
+    pub async fn get_log(
+        sqlite: &SqlitePool,
+        filter: &RequestLogFilter,
+    ) -> Result<Vec<Self>, AppError> {
+        let sql = "SELECT * FROM request WHERE ($1 IS NULL OR channel = $1) AND ($2 IS NULL OR kind = $2) AND ($3 IS NULL OR alarm_id = $3) AND ($4 IS NULL OR recipient = $4) AND ($5 IS NULL OR delivered = $5) AND ($6 IS NULL OR timestamp >= $6) AND ($7 IS NULL OR timestamp <= $7) ORDER BY request_id DESC LIMIT $8";
+        let result = sqlx::query_as::<_, Self>(sql)
+            .bind(filter.channel.map(Channel::as_str))
+            .bind(filter.kind.map(RequestKind::as_str))
+            .bind(filter.alarm_id)
+            .bind(&filter.recipient)
+            .bind(filter.delivered)
+            .bind(filter.from)
+            .bind(filter.to)
+            .bind(filter.limit)
+            .fetch_all(sqlite)
+            .await?;
+        Ok(result)
+    }
+
+    /// Remove every request older than `max_age` seconds, returns the number of rows removed
+    pub async fn delete_older_than(sqlite: &SqlitePool, max_age: u64) -> Result<u64, AppError> {
+        let sql = "DELETE FROM request WHERE timestamp < $1";
+        let result = sqlx::query(sql)
+            .bind(Self::now_i64() - i64::try_from(max_age).unwrap_or(i64::MAX))
+            .execute(sqlite)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Only keep the most recent `max_rows` requests, but never remove any younger than `min_age` seconds, returns the number of rows removed
+    pub async fn delete_excess(
+        sqlite: &SqlitePool,
+        max_rows: u64,
+        min_age: u64,
+    ) -> Result<u64, AppError> {
+        let sql = "DELETE FROM request WHERE timestamp <= $1 AND request_id <= (SELECT request_id FROM request ORDER BY request_id DESC LIMIT 1 OFFSET $2)";
+        let result = sqlx::query(sql)
+            .bind(Self::now_i64() - i64::try_from(min_age).unwrap_or(i64::MAX))
+            .bind(i64::try_from(max_rows).unwrap_or(i64::MAX))
+            .execute(sqlite)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
     #[cfg(test)]
     pub async fn test_get_all(sqlite: &SqlitePool) -> Result<Vec<Self>, AppError> {
         let sql = "SELECT * FROM request";
@@ -101,7 +258,13 @@ impl ModelRequest {
 #[cfg(test)]
 #[expect(clippy::unwrap_used)]
 mod tests {
-    use crate::tests::{test_cleanup, test_setup};
+    use crate::{
+        C, S,
+        app_env::DEFAULT_RECIPIENT,
+        db::ModelAlarm,
+        tests::{test_cleanup, test_setup},
+        ws_messages::HourMinuteMsg,
+    };
 
     use super::*;
 
@@ -110,7 +273,14 @@ mod tests {
         let (_app_envs, sqlite, uuid) = test_setup().await;
 
         let now = ModelRequest::now();
-        let result = ModelRequest::insert(&sqlite, &PushRequest::Alarm(0)).await;
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await;
 
         assert!(result.is_ok());
         let result = result.unwrap();
@@ -118,13 +288,437 @@ mod tests {
         assert_eq!(result.timestamp, now);
         assert!(result.is_alarm);
 
-        let result = ModelRequest::insert(&sqlite, &PushRequest::TestRequest).await;
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::TestRequest,
+            Channel::Pushover,
+            "jack",
+            "",
+        )
+        .await;
 
         assert!(result.is_ok());
         let result = result.unwrap();
         assert_eq!(result.request_id, 2);
         assert!(!result.is_alarm);
         assert_eq!(result.timestamp, now);
+        assert_eq!(result.recipient, "jack");
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Alarm requests are linked to the alarm that was ringing, even once it's been replaced, and the message is truncated
+    async fn model_request_add_log_fields() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        ModelAlarm::add(&sqlite, HourMinuteMsg::from((6, 30, None)))
+            .await
+            .unwrap();
+        let alarm = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        ModelAlarm::delete(&sqlite).await.unwrap();
+        ModelAlarm::add(&sqlite, HourMinuteMsg::from((7, 0, None)))
+            .await
+            .unwrap();
+
+        let message = "a".repeat(150);
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::Alarm {
+                alarm_id: alarm.alarm_id,
+                push_index: 7,
+            },
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            &message,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.kind, RequestKind::Alarm);
+        assert_eq!(result.alarm_id, Some(alarm.alarm_id));
+        assert_eq!(result.push_index, Some(7));
+        assert_eq!(result.message, Some("a".repeat(MAX_MESSAGE_LEN)));
+        assert!(result.latency_ms.is_none());
+
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::TestRequest,
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "test",
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.kind, RequestKind::TestRequest);
+        assert!(result.alarm_id.is_none());
+        assert!(result.push_index.is_none());
+        assert_eq!(result.message.as_deref(), Some("test"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // The kind is derived from is_alarm, so the two can never disagree, even if only is_alarm is given
+    async fn model_request_kind_derived() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for is_alarm in [true, false] {
+            sqlx::query("INSERT INTO request(timestamp, is_alarm) VALUES ($1, $2)")
+                .bind(ModelRequest::now_i64())
+                .bind(is_alarm)
+                .execute(&sqlite)
+                .await
+                .unwrap();
+        }
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result[0].kind, RequestKind::Alarm);
+        assert_eq!(result[1].kind, RequestKind::TestRequest);
+
+        let result = sqlx::query("UPDATE request SET kind = 'alarm'")
+            .execute(&sqlite)
+            .await;
+        assert!(result.is_err());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_get_log() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for (push_request, channel, recipient) in [
+            (
+                PushRequest::test_alarm(1),
+                Channel::Pushover,
+                DEFAULT_RECIPIENT,
+            ),
+            (PushRequest::test_alarm(1), Channel::Pushover, "jack"),
+            (PushRequest::test_alarm(1), Channel::Telegram, ""),
+            (
+                PushRequest::TestRequest,
+                Channel::Pushover,
+                DEFAULT_RECIPIENT,
+            ),
+        ] {
+            ModelRequest::insert(&sqlite, &push_request, channel, recipient, "msg")
+                .await
+                .unwrap();
+        }
+        ModelRequest::set_delivered(&sqlite, 2, Duration::ZERO)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE request SET timestamp = 100 WHERE request_id = 1")
+            .execute(&sqlite)
+            .await
+            .unwrap();
+
+        let get_ids = |filter: RequestLogFilter| {
+            let sqlite = C!(sqlite);
+            async move {
+                ModelRequest::get_log(&sqlite, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|i| i.request_id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(get_ids(RequestLogFilter::default()).await, [4, 3, 2, 1]);
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                limit: 2,
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [4, 3]
+        );
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                channel: Some(Channel::Pushover),
+                kind: Some(RequestKind::Alarm),
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [2, 1]
+        );
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                recipient: Some(S!("jack")),
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [2]
+        );
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                delivered: Some(false),
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [4, 3, 1]
+        );
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                from: Some(50),
+                to: Some(150),
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [1]
+        );
+        assert_eq!(
+            get_ids(RequestLogFilter {
+                alarm_id: Some(1),
+                ..RequestLogFilter::default()
+            })
+            .await,
+            [3, 2, 1]
+        );
+        assert!(
+            get_ids(RequestLogFilter {
+                alarm_id: Some(2),
+                ..RequestLogFilter::default()
+            })
+            .await
+            .is_empty()
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_delete_older_than() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for age in [10, 100, 1000] {
+            sqlx::query("INSERT INTO request(timestamp, is_alarm) VALUES ($1, true)")
+                .bind(ModelRequest::now_i64() - age)
+                .execute(&sqlite)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            ModelRequest::delete_older_than(&sqlite, 500).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            ModelRequest::delete_older_than(&sqlite, 500).await.unwrap(),
+            0
+        );
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 2);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_delete_excess() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+        for _ in 0..5 {
+            ModelRequest::insert(
+                &sqlite,
+                &PushRequest::test_alarm(0),
+                Channel::Pushover,
+                DEFAULT_RECIPIENT,
+                "",
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            ModelRequest::delete_excess(&sqlite, 10, 0).await.unwrap(),
+            0
+        );
+        // Every row is too recent
+        assert_eq!(
+            ModelRequest::delete_excess(&sqlite, 2, 60).await.unwrap(),
+            0
+        );
+        assert_eq!(ModelRequest::delete_excess(&sqlite, 2, 0).await.unwrap(), 3);
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(
+            result.iter().map(|i| i.request_id).collect::<Vec<_>>(),
+            [4, 5]
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_set_delivered() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Matrix,
+            "",
+            "",
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.channel, Channel::Matrix);
+        assert!(!result.delivered);
+
+        ModelRequest::set_delivered(&sqlite, result.request_id, Duration::from_millis(120))
+            .await
+            .unwrap();
+
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert!(result[0].delivered);
+        assert_eq!(result[0].latency_ms, Some(120));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_set_response() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        let request = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await
+        .unwrap();
+        assert!(request.http_status.is_none());
+
+        let response = serde_json::from_str::<PostResponse>(
+            r#"{"http_status":400,"status":0,"request":"abc","errors":["user is invalid","message is blank"],"limit_remaining":10,"limit_reset":1393653600,"invalid_token":false,"invalid_user":true}"#,
+        )
+        .unwrap();
+        ModelRequest::set_response(&sqlite, request.request_id, &response, Duration::ZERO)
+            .await
+            .unwrap();
+
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert!(!result[0].delivered);
+        assert_eq!(result[0].http_status, Some(400));
+        assert_eq!(result[0].api_status, Some(0));
+        assert_eq!(result[0].provider_request_id.as_deref(), Some("abc"));
+        assert_eq!(
+            result[0].errors.as_deref(),
+            Some("user is invalid, message is blank")
+        );
+        assert_eq!(result[0].limit_remaining, Some(10));
+        assert_eq!(result[0].limit_reset, Some(1_393_653_600));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_request_set_error() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        let request = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await
+        .unwrap();
+        ModelRequest::set_error(
+            &sqlite,
+            request.request_id,
+            "operation timed out",
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert!(!result[0].delivered);
+        assert!(result[0].http_status.is_none());
+        assert_eq!(result[0].errors.as_deref(), Some("operation timed out"));
+        assert_eq!(result[0].latency_ms, Some(10_000));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Four requests inserted, one over a day ago, the hourly and daily counts are returned together
+    async fn model_request_get_last_day() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        for minutes in [10, 50, 120, 60 * 25] {
+            let sql = "INSERT INTO request(timestamp, is_alarm) VALUES ($1, true)";
+            sqlx::query(sql)
+                .bind(ModelRequest::now_i64() - (60 * minutes))
+                .execute(&sqlite)
+                .await
+                .unwrap();
+        }
+
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, RequestCount { hour: 2, day: 3 });
+
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::TestRequest,
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, RequestCount::default());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // The count query uses the index, rather than scanning the whole table
+    async fn model_request_count_uses_index() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        let result = sqlx::query_as::<_, (i64, i64, i64, String)>(
+            "EXPLAIN QUERY PLAN SELECT COUNT(*) AS day, COALESCE(SUM(timestamp >= $1), 0) AS hour FROM request WHERE channel = $2 AND recipient = $3 AND is_alarm = $4 AND timestamp BETWEEN $5 AND $6",
+        )
+        .bind(0)
+        .bind("pushover")
+        .bind(DEFAULT_RECIPIENT)
+        .bind(true)
+        .bind(0)
+        .bind(0)
+        .fetch_all(&sqlite)
+        .await
+        .unwrap();
+        assert!(result.iter().any(|i| i.3.contains("INDEX request_limit")));
+        assert!(!result.iter().any(|i| i.3 == "SCAN request"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Requests are counted per channel
+    async fn model_request_get_last_hour_channel() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Telegram,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await
+        .unwrap();
+
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await;
+        assert_eq!(result.unwrap().hour, 0);
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Telegram,
+            DEFAULT_RECIPIENT,
+        )
+        .await;
+        assert_eq!(result.unwrap().hour, 1);
         test_cleanup(uuid, Some(sqlite)).await;
     }
 
@@ -133,7 +727,14 @@ mod tests {
         let (_app_envs, sqlite, uuid) = test_setup().await;
 
         let now = ModelRequest::now();
-        let result = ModelRequest::insert(&sqlite, &PushRequest::Alarm(0)).await;
+        let result = ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await;
 
         assert!(result.is_ok());
         let result = result.unwrap();
@@ -177,6 +778,60 @@ mod tests {
         test_cleanup(uuid, Some(sqlite)).await;
     }
 
+    #[tokio::test]
+    // Requests are counted per recipient
+    async fn model_request_get_last_hour_recipient() {
+        let (_app_envs, sqlite, uuid) = test_setup().await;
+
+        for _ in 0..3 {
+            ModelRequest::insert(
+                &sqlite,
+                &PushRequest::test_alarm(0),
+                Channel::Pushover,
+                "jack",
+                "",
+            )
+            .await
+            .unwrap();
+        }
+        ModelRequest::insert(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+            "",
+        )
+        .await
+        .unwrap();
+
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            "jack",
+        )
+        .await;
+        assert_eq!(result.unwrap().hour, 3);
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await;
+        assert_eq!(result.unwrap().hour, 1);
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            "sam",
+        )
+        .await;
+        assert_eq!(result.unwrap().hour, 0);
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
     #[tokio::test]
     // Four requests inserted, two over an hour ago
     async fn model_request_get_last_hour_alarm() {
@@ -200,12 +855,24 @@ mod tests {
                 .unwrap();
         }
 
-        let result = ModelRequest::count_past_hour(&sqlite, &PushRequest::Alarm(0)).await;
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::test_alarm(0),
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 2);
-        let result = ModelRequest::count_past_hour(&sqlite, &PushRequest::TestRequest).await;
+        assert_eq!(result.unwrap().hour, 2);
+        let result = ModelRequest::count_recent(
+            &sqlite,
+            &PushRequest::TestRequest,
+            Channel::Pushover,
+            DEFAULT_RECIPIENT,
+        )
+        .await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 2);
+        assert_eq!(result.unwrap().hour, 2);
 
         test_cleanup(uuid, Some(sqlite)).await;
     }
@@ -0,0 +1,190 @@
+use sqlx::SqlitePool;
+use std::fmt;
+
+use crate::{
+    C, S, app_env::AppEnv, app_error::AppError, db::ModelRequest, ws_messages::CredentialsUpdate,
+};
+
+const TOKEN_APP: &str = "pushover_token_app";
+const USER_PREFIX: &str = "pushover_user:";
+
+/// A single runtime setting, which takes precedence over the matching env value.
+/// Values are credentials, so are never included in `Debug` or `Display` output
+#[derive(sqlx::FromRow, Clone, PartialEq, Eq)]
+pub struct ModelSetting {
+    pub setting_id: i64,
+    pub name: String,
+    pub value: String,
+    #[sqlx(try_from = "i64")]
+    pub timestamp: u64,
+}
+
+impl fmt::Debug for ModelSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ModelSetting")
+            .field("setting_id", &self.setting_id)
+            .field("name", &self.name)
+            .field("timestamp", &self.timestamp)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ModelSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "setting_id: {}, name: {}, timestamp: {}",
+            self.setting_id, self.name, self.timestamp
+        )
+    }
+}
+
+impl ModelSetting {
+    pub async fn get_all(sqlite: &SqlitePool) -> Result<Vec<Self>, AppError> {
+        let sql = "SELECT * FROM setting ORDER BY setting_id";
+        Ok(sqlx::query_as::<_, Self>(sql).fetch_all(sqlite).await?)
+    }
+
+    /// The env values, with any stored credentials applied on top
+    pub async fn apply(sqlite: &SqlitePool, app_envs: &AppEnv) -> Result<AppEnv, AppError> {
+        let mut output = C!(app_envs);
+        for setting in Self::get_all(sqlite).await? {
+            if setting.name == TOKEN_APP {
+                output.token_app = setting.value;
+            } else if let Some(name) = setting.name.strip_prefix(USER_PREFIX)
+                && let Some(recipient) = output.recipients.iter_mut().find(|i| i.name == name)
+            {
+                recipient.user = setting.value;
+            }
+        }
+        Ok(output)
+    }
+
+    /// Store every credential in the update, in a single transaction
+    pub async fn set_credentials(
+        sqlite: &SqlitePool,
+        update: &CredentialsUpdate,
+    ) -> Result<(), AppError> {
+        let values = update
+            .token_app
+            .iter()
+            .map(|token| (S!(TOKEN_APP), token))
+            .chain(
+                update
+                    .users
+                    .iter()
+                    .map(|(name, user)| (format!("{USER_PREFIX}{name}"), user)),
+            );
+        let sql = "INSERT INTO setting(name, value, timestamp) VALUES ($1, $2, $3) ON CONFLICT(name) DO UPDATE SET value = excluded.value, timestamp = excluded.timestamp";
+        let mut transaction = sqlite.begin().await?;
+        for (name, value) in values {
+            sqlx::query(sql)
+                .bind(name)
+                .bind(value)
+                .bind(ModelRequest::now_i64())
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Remove every stored credential, so that the env values are used again
+    pub async fn delete_credentials(sqlite: &SqlitePool) -> Result<u64, AppError> {
+        let sql = "DELETE FROM setting WHERE name = $1 OR substr(name, 1, length($2)) = $2";
+        Ok(sqlx::query(sql)
+            .bind(TOKEN_APP)
+            .bind(USER_PREFIX)
+            .execute(sqlite)
+            .await?
+            .rows_affected())
+    }
+}
+
+/// model_setting
+///
+/// cargo watch -q -c -w src/ -x 'test model_setting_ -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{
+        app_env::DEFAULT_RECIPIENT,
+        tests::{test_cleanup, test_setup},
+    };
+
+    fn gen_update(token_app: Option<&str>, users: &[(&str, &str)]) -> CredentialsUpdate {
+        CredentialsUpdate {
+            token_app: token_app.map(ToOwned::to_owned),
+            users: users
+                .iter()
+                .map(|(name, user)| (S!(*name), S!(*user)))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[tokio::test]
+    // Env values are used when nothing is stored
+    async fn model_setting_apply_fallback() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+
+        let result = ModelSetting::apply(&sqlite, &app_envs).await.unwrap();
+        assert_eq!(result.token_app, app_envs.token_app);
+        assert_eq!(result.recipients, app_envs.recipients);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_setting_set_credentials() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+
+        ModelSetting::set_credentials(&sqlite, &gen_update(Some("token_one"), &[]))
+            .await
+            .unwrap();
+        let result = ModelSetting::apply(&sqlite, &app_envs).await.unwrap();
+        assert_eq!(result.token_app, "token_one");
+        assert_eq!(result.recipients[0].user, app_envs.recipients[0].user);
+
+        // Existing values are replaced, users for unknown recipients are ignored
+        ModelSetting::set_credentials(
+            &sqlite,
+            &gen_update(
+                Some("token_two"),
+                &[(DEFAULT_RECIPIENT, "user_one"), ("unknown", "user_two")],
+            ),
+        )
+        .await
+        .unwrap();
+        let result = ModelSetting::apply(&sqlite, &app_envs).await.unwrap();
+        assert_eq!(result.token_app, "token_two");
+        assert_eq!(result.recipients.len(), 1);
+        assert_eq!(result.recipients[0].user, "user_one");
+        assert_eq!(ModelSetting::get_all(&sqlite).await.unwrap().len(), 3);
+
+        // Values are never displayed
+        let setting = &ModelSetting::get_all(&sqlite).await.unwrap()[0];
+        assert!(!format!("{setting:?}").contains("token_two"));
+        assert!(!setting.to_string().contains("token_two"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn model_setting_delete_credentials() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        ModelSetting::set_credentials(
+            &sqlite,
+            &gen_update(Some("token"), &[(DEFAULT_RECIPIENT, "user")]),
+        )
+        .await
+        .unwrap();
+
+        let result = ModelSetting::delete_credentials(&sqlite).await.unwrap();
+        assert_eq!(result, 2);
+        let result = ModelSetting::apply(&sqlite, &app_envs).await.unwrap();
+        assert_eq!(result.token_app, app_envs.token_app);
+        assert_eq!(result.recipients, app_envs.recipients);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
@@ -0,0 +1,324 @@
+use std::time::{Duration, Instant};
+
+use sqlx::SqlitePool;
+use tokio::sync::watch;
+
+use crate::{
+    C, S,
+    app_env::AppEnv,
+    app_error::AppError,
+    credentials::CredentialStatus,
+    db::{ModelAlarm, ModelSetting},
+    request::{PostResponse, PushRequest},
+};
+
+/// Path of the glances endpoint, relative to the configurable api base url
+const GLANCES_PATH: &str = "1/glances.json";
+
+/// Title, text, and subtext are each limited to 100 characters by the glances api
+const MAX_FIELD_LEN: usize = 100;
+
+const TITLE: &str = "Next alarm";
+
+/// Send the latest glance data to the glance worker, a send never fails, even if glances aren't configured
+pub type GlanceSender = watch::Sender<GlanceData>;
+
+/// The content of the glance, every field empty clears the glance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlanceData {
+    pub title: String,
+    pub text: String,
+    pub subtext: String,
+}
+
+impl GlanceData {
+    fn truncate(input: &str) -> String {
+        input.chars().take(MAX_FIELD_LEN).collect()
+    }
+
+    /// The next alarm time, and its message as the label, or an empty glance if no alarm is set
+    pub fn from_alarm(alarm: Option<&ModelAlarm>) -> Self {
+        alarm.map_or_else(Self::default, |alarm| Self {
+            title: S!(TITLE),
+            text: format!("{:02}:{:02}", alarm.hour, alarm.minute),
+            subtext: Self::truncate(alarm.message.as_deref().unwrap_or_default()),
+        })
+    }
+}
+
+/// Keep a Pushover glance up to date with the next alarm.
+/// Updates are sent at most once every `GLANCE_INTERVAL` seconds, any changes made in the meantime are coalesced into a single update
+#[derive(Debug, Clone)]
+pub struct Glance {
+    app_envs: AppEnv,
+    interval: Duration,
+    recipient: String,
+    sqlite: SqlitePool,
+}
+
+impl Glance {
+    /// Only created if a glance recipient has been configured
+    pub fn new(app_envs: &AppEnv, sqlite: &SqlitePool) -> Option<Self> {
+        let name = app_envs.glance_recipient.as_ref()?;
+        let recipient = app_envs.recipients.iter().find(|i| &i.name == name)?;
+        Some(Self {
+            app_envs: C!(app_envs),
+            interval: Duration::from_secs(app_envs.glance_interval),
+            recipient: C!(recipient.name),
+            sqlite: C!(sqlite),
+        })
+    }
+
+    /// A single update, empty fields are sent, rather than omitted, so that the glance gets cleared.
+    /// Credentials are loaded on every update, so that any stored at runtime are used
+    async fn send(&self, data: &GlanceData) -> Result<(), AppError> {
+        let app_envs = ModelSetting::apply(&self.sqlite, &self.app_envs).await?;
+        let recipient = app_envs
+            .recipients
+            .iter()
+            .find(|i| i.name == self.recipient)
+            .ok_or_else(|| AppError::RecipientInvalid(C!(self.recipient)))?;
+        let mut params = vec![
+            ("token", C!(app_envs.token_app)),
+            ("user", C!(recipient.user)),
+            ("title", C!(data.title)),
+            ("text", C!(data.text)),
+            ("subtext", C!(data.subtext)),
+        ];
+        if !recipient.devices.is_empty() {
+            params.push(("device", recipient.devices.join(",")));
+        }
+        let url = reqwest::Url::parse_with_params(
+            &format!(
+                "{}/{GLANCES_PATH}",
+                app_envs.pushover_url.trim_end_matches('/')
+            ),
+            &params,
+        )?;
+        let response = PushRequest::get_client(&app_envs)?.post(url).send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        Ok(PostResponse::from_parts(status, &headers, &body).check()?)
+    }
+
+    /// Wait for a change, send it, and then wait out the interval before checking for any further changes.
+    /// A failed update is retried after the interval, unless the error is fatal, in which case it's retried once the credentials change
+    async fn run(
+        self,
+        mut rx: watch::Receiver<GlanceData>,
+        mut credentials: watch::Receiver<Option<CredentialStatus>>,
+    ) {
+        let mut sent = None;
+        while rx.changed().await.is_ok() {
+            loop {
+                let data = rx.borrow_and_update().clone();
+                if sent.as_ref() == Some(&data) {
+                    break;
+                }
+                let start = Instant::now();
+                match self.send(&data).await {
+                    Ok(()) => {
+                        tracing::debug!("glance updated: {data:?}");
+                        sent = Some(data);
+                    }
+                    Err(e) if e.is_fatal() => {
+                        tracing::error!("glance paused until the credentials change: {e}");
+                        if credentials.changed().await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Err(e) => tracing::error!("glance: {e}"),
+                }
+                tokio::time::sleep(self.interval.saturating_sub(start.elapsed())).await;
+            }
+        }
+    }
+
+    /// Spawn the glance worker onto its own tokio thread, returning the sender used to update it
+    pub fn spawn(self, credentials: watch::Receiver<Option<CredentialStatus>>) -> GlanceSender {
+        let (sx, rx) = watch::channel(GlanceData::default());
+        tokio::spawn(self.run(rx, credentials));
+        sx
+    }
+}
+
+/// glance
+///
+/// cargo watch -q -c -w src/ -x 'test glance_ -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_env::DEFAULT_RECIPIENT,
+        credentials::CredentialSender,
+        mock_server::{MockResponse, MockServer},
+        tests::{test_cleanup, test_setup},
+        ws_messages::{CredentialsUpdate, PushOptions},
+    };
+    use uuid::Uuid;
+
+    fn gen_alarm(message: Option<&str>) -> ModelAlarm {
+        ModelAlarm {
+            alarm_id: 1,
+            hour: 7,
+            minute: 5,
+            message: message.map(ToOwned::to_owned),
+            recipients: S!().into(),
+            options: PushOptions::default(),
+        }
+    }
+
+    async fn gen_glance() -> (Glance, MockServer, SqlitePool, Uuid) {
+        let server = MockServer::start().await;
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.pushover_url = server.url();
+        app_envs.glance_recipient = Some(S!(DEFAULT_RECIPIENT));
+        let mut glance = Glance::new(&app_envs, &sqlite).unwrap();
+        glance.interval = Duration::from_millis(250);
+        (glance, server, sqlite, uuid)
+    }
+
+    #[test]
+    fn glance_data_from_alarm() {
+        assert_eq!(GlanceData::from_alarm(None), GlanceData::default());
+
+        let result = GlanceData::from_alarm(Some(&gen_alarm(Some("wake up"))));
+        assert_eq!(result.title, TITLE);
+        assert_eq!(result.text, "07:05");
+        assert_eq!(result.subtext, "wake up");
+
+        let result = GlanceData::from_alarm(Some(&gen_alarm(None)));
+        assert_eq!(result.subtext, "");
+
+        let long = "a".repeat(150);
+        let result = GlanceData::from_alarm(Some(&gen_alarm(Some(&long))));
+        assert_eq!(result.subtext.len(), MAX_FIELD_LEN);
+    }
+
+    #[tokio::test]
+    async fn glance_new() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        assert!(Glance::new(&app_envs, &sqlite).is_none());
+
+        app_envs.glance_recipient = Some(S!("unknown"));
+        assert!(Glance::new(&app_envs, &sqlite).is_none());
+
+        app_envs.glance_recipient = Some(S!(DEFAULT_RECIPIENT));
+        let result = Glance::new(&app_envs, &sqlite).unwrap();
+        assert_eq!(result.recipient, DEFAULT_RECIPIENT);
+        assert_eq!(result.interval, Duration::from_secs(300));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn glance_send() {
+        let (glance, server, sqlite, uuid) = gen_glance().await;
+
+        let result = glance
+            .send(&GlanceData::from_alarm(Some(&gen_alarm(Some("wake up")))))
+            .await;
+        assert!(result.is_ok());
+
+        // Cleared, every field sent empty
+        let result = glance.send(&GlanceData::default()).await;
+        assert!(result.is_ok());
+
+        let received = server.requests();
+        assert_eq!(received.len(), 2);
+        assert!(received[0].path.starts_with("/1/glances.json?"));
+        assert!(received[0].path.contains("user=test_token_user"));
+        assert!(received[0].path.contains("title=Next+alarm"));
+        assert!(received[0].path.contains("text=07%3A05"));
+        assert!(received[0].path.contains("subtext=wake+up"));
+        assert!(received[1].path.contains("title=&text=&subtext="));
+
+        server.push(MockResponse::new(
+            400,
+            r#"{"status":0,"errors":["title is too long"]}"#,
+        ));
+        let result = glance.send(&GlanceData::default()).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Pushover: rejected, status 400: title is too long"
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Credentials stored at runtime are used instead of the env values
+    async fn glance_send_stored_credentials() {
+        let (glance, server, sqlite, uuid) = gen_glance().await;
+        ModelSetting::set_credentials(
+            &sqlite,
+            &CredentialsUpdate {
+                token_app: Some(S!("stored_token")),
+                users: [(S!(DEFAULT_RECIPIENT), S!("stored_user"))].into(),
+            },
+        )
+        .await
+        .unwrap();
+
+        glance.send(&GlanceData::default()).await.unwrap();
+
+        let received = server.requests();
+        assert!(received[0].path.contains("token=stored_token"));
+        assert!(received[0].path.contains("user=stored_user"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Changes made within the interval are coalesced, and only the latest is sent, unchanged data isn't resent
+    async fn glance_run_rate_limited() {
+        let (glance, server, sqlite, uuid) = gen_glance().await;
+        let credentials = CredentialSender::new(None);
+        let sx = glance.spawn(credentials.subscribe());
+
+        sx.send_replace(GlanceData::from_alarm(Some(&gen_alarm(Some("one")))));
+        crate::sleep!(50);
+        sx.send_replace(GlanceData::from_alarm(Some(&gen_alarm(Some("two")))));
+        sx.send_replace(GlanceData::from_alarm(Some(&gen_alarm(Some("three")))));
+        crate::sleep!(100);
+        assert_eq!(server.requests().len(), 1);
+
+        crate::sleep!(250);
+        let received = server.requests();
+        assert_eq!(received.len(), 2);
+        assert!(received[0].path.contains("subtext=one"));
+        assert!(received[1].path.contains("subtext=three"));
+
+        sx.send_replace(GlanceData::from_alarm(Some(&gen_alarm(Some("three")))));
+        crate::sleep!(400);
+        assert_eq!(server.requests().len(), 2);
+
+        sx.send_replace(GlanceData::default());
+        crate::sleep!(100);
+        assert_eq!(server.requests().len(), 3);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // An invalid token pauses the worker, rather than stopping it, once the credentials change the update is sent
+    async fn glance_run_fatal_resumes() {
+        let (glance, server, sqlite, uuid) = gen_glance().await;
+        server.push(MockResponse::new(
+            400,
+            r#"{"status":0,"token":"invalid","errors":["application token is invalid"]}"#,
+        ));
+        let credentials = CredentialSender::new(None);
+        let sx = glance.spawn(credentials.subscribe());
+
+        sx.send_replace(GlanceData::from_alarm(Some(&gen_alarm(Some("one")))));
+        crate::sleep!(400);
+        assert_eq!(server.requests().len(), 1);
+
+        credentials.send_replace(None);
+        crate::sleep!(100);
+        let received = server.requests();
+        assert_eq!(received.len(), 2);
+        assert!(received[1].path.contains("subtext=one"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
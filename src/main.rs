@@ -6,7 +6,15 @@ static GLOBAL: MiMalloc = MiMalloc;
 mod alarm_schedule;
 mod app_env;
 mod app_error;
+mod backoff;
+mod credentials;
 mod db;
+mod glance;
+mod maintenance;
+#[cfg(test)]
+mod mock_server;
+mod notify;
+mod outbox;
 mod request;
 mod sysinfo;
 mod word_art;
@@ -16,9 +24,13 @@ mod ws_messages;
 use alarm_schedule::AlarmSchedule;
 use app_env::AppEnv;
 use app_error::AppError;
+use credentials::{CredentialSender, CredentialStatus};
 use db::init_db;
+use maintenance::Maintenance;
+use notify::Telegram;
+use outbox::Outbox;
 use word_art::Intro;
-use ws::open_connection;
+use ws::{EventQueue, open_connection};
 
 use crate::db::ModelObliqueStrategy;
 
@@ -61,12 +73,25 @@ async fn start() -> Result<(), AppError> {
     let sqlite = init_db(&app_envs).await?;
     ModelObliqueStrategy::seed_stratergies(&sqlite).await?;
     close_signal();
-    let sx = AlarmSchedule::init(C!(sqlite), C!(app_envs)).await?;
-    open_connection(app_envs, sqlite, sx).await?;
+    let credentials = CredentialSender::new(CredentialStatus::startup(&app_envs, &sqlite).await?);
+    let (event_sx, _) = tokio::sync::broadcast::channel(32);
+    // Subscribed before the AlarmSchedule starts, so that the events it sends at startup are kept for the first client
+    let event_queue = EventQueue::spawn(&app_envs, &event_sx);
+    let sx = AlarmSchedule::init(C!(sqlite), C!(app_envs), C!(event_sx), &credentials).await?;
+    if let Some(telegram) = Telegram::new(&app_envs) {
+        telegram.spawn_poll(C!(sx));
+    }
+    Outbox::new(&app_envs, &sqlite).spawn();
+    Maintenance::new(&app_envs, &sqlite).spawn();
+    open_connection(app_envs, sqlite, sx, event_queue, credentials).await?;
     Ok(())
 }
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
+    if std::env::args().nth(1).as_deref() == Some("schema") {
+        print!("{}", ws_messages::protocol_schema());
+        return Ok(());
+    }
     tokio::spawn(async move {
         if let Err(e) = start().await {
             tracing::error!("{e:?}");
@@ -80,13 +105,22 @@ async fn main() -> Result<(), AppError> {
 #[cfg(test)]
 #[expect(clippy::unwrap_used)]
 mod tests {
-    use std::{path::PathBuf, time::SystemTime};
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
 
     use jiff::tz::TimeZone;
     use sqlx::SqlitePool;
     use uuid::Uuid;
 
-    use crate::{app_env::AppEnv, db::init_db};
+    use crate::{
+        app_env::{AppEnv, DEFAULT_RECIPIENT, Recipient},
+        backoff::Backoff,
+        db::init_db,
+        mock_server::MockServer,
+        notify::Channel,
+    };
     /// Close database connection, and delete all test files
     pub async fn test_cleanup(uuid: Uuid, sqlite: Option<SqlitePool>) {
         if let Some(sqlite) = sqlite {
@@ -103,22 +137,49 @@ mod tests {
     /// The uuid is used as a file location for sqlite, at /dev/shm/{ uuid }.db
     pub fn gen_app_envs(uuid: Uuid) -> AppEnv {
         AppEnv {
+            glance_interval: 300,
+            glance_recipient: None,
+            limits: std::collections::HashMap::new(),
             location_sqlite: format!("/dev/shm/{uuid}.db"),
             log_level: tracing::Level::INFO,
+            matrix_access_token: None,
+            matrix_homeserver: None,
+            matrix_room_id: None,
+            notify_chain: vec![Channel::Pushover],
+            outbox_max_age: 300,
+            pushover_timeout_ms: 10_000,
+            pushover_url: S!("http://127.0.0.1:0"),
+            pushover_validate_strict: false,
+            recipients: vec![Recipient {
+                name: S!(DEFAULT_RECIPIENT),
+                user: S!("test_token_user"),
+                devices: vec![],
+            }],
+            request_retention_days: 90,
+            request_retention_rows: 50_000,
             start_time: SystemTime::now(),
+            telegram_chat_id: None,
+            telegram_token: None,
+            telegram_url: S!("http://127.0.0.1:0"),
             timezone: TimeZone::get("Europe/London").unwrap(),
             token_app: S!("test_token_app"),
-            token_user: S!("test_token_user"),
             ws_address: S!("ws_address"),
             ws_apikey: S!("ws_apikey"),
+            ws_command_timeout: 60,
+            ws_event_max_age: 300,
             ws_password: S!("ws_password"),
+            ws_queue_depth: 16,
+            ws_reconnect: Backoff::new(Duration::from_secs(1), Duration::from_secs(300)),
+            ws_reconnect_auth: Backoff::new(Duration::from_secs(10), Duration::from_secs(300)),
             ws_token_address: S!("ws_token_address"),
         }
     }
 
+    /// Pushover requests are made against an in-process mock server, which accepts every request
     pub async fn test_setup() -> (AppEnv, SqlitePool, Uuid) {
         let uuid = Uuid::new_v4();
-        let app_envs = gen_app_envs(uuid);
+        let mut app_envs = gen_app_envs(uuid);
+        app_envs.pushover_url = MockServer::start().await.url();
         let sqlite = init_db(&app_envs).await.unwrap();
         (app_envs, sqlite, uuid)
     }
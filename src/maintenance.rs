@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+
+use crate::{
+    C,
+    app_env::AppEnv,
+    app_error::AppError,
+    db::{ModelRequest, optimize, vacuum},
+};
+
+/// How often old requests are pruned, and the query planner statistics updated
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// VACUUM rewrites the whole database file, so only run it on every nth maintenance run, roughly once a day
+const VACUUM_EVERY: u32 = 24;
+
+const ONE_DAY_AS_SEC: u64 = 60 * 60 * 24;
+
+/// Keep the request table from growing forever
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    app_envs: AppEnv,
+    sqlite: SqlitePool,
+}
+
+impl Maintenance {
+    pub fn new(app_envs: &AppEnv, sqlite: &SqlitePool) -> Self {
+        Self {
+            app_envs: C!(app_envs),
+            sqlite: C!(sqlite),
+        }
+    }
+
+    /// Remove requests older than the retention age, and then any over the retention row count, returns the number of rows removed.
+    /// The last day of requests is always kept, else the hourly and daily rate limits would be reset
+    async fn prune(&self) -> Result<u64, AppError> {
+        let by_age = ModelRequest::delete_older_than(
+            &self.sqlite,
+            self.app_envs
+                .request_retention_days
+                .saturating_mul(ONE_DAY_AS_SEC),
+        )
+        .await?;
+        let by_rows = ModelRequest::delete_excess(
+            &self.sqlite,
+            self.app_envs.request_retention_rows,
+            ONE_DAY_AS_SEC,
+        )
+        .await?;
+        tracing::debug!("requests pruned, {by_age} by age, {by_rows} by row count");
+        Ok(by_age + by_rows)
+    }
+
+    /// A single maintenance run, optionally followed by a VACUUM
+    async fn run_once(&self, with_vacuum: bool) -> Result<(), AppError> {
+        self.prune().await?;
+        optimize(&self.sqlite).await?;
+        tracing::debug!("optimize complete");
+        if with_vacuum {
+            let (before, after) = vacuum(&self.sqlite).await?;
+            tracing::debug!(
+                "vacuum complete, {} bytes -> {} bytes, {} free pages reclaimed",
+                before.bytes(),
+                after.bytes(),
+                before.freelist_count - after.freelist_count
+            );
+        }
+        Ok(())
+    }
+
+    async fn run(self) {
+        let mut count = 0u32;
+        loop {
+            tokio::time::sleep(MAINTENANCE_INTERVAL).await;
+            count = count.wrapping_add(1);
+            if let Err(e) = self.run_once(count.is_multiple_of(VACUUM_EVERY)).await {
+                tracing::error!("{e}");
+            }
+        }
+    }
+
+    /// Spawn the maintenance task onto its own tokio thread
+    pub fn spawn(self) {
+        tokio::spawn(self.run());
+    }
+}
+
+/// maintenance
+///
+/// cargo watch -q -c -w src/ -x 'test maintenance_ -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_cleanup, test_setup};
+
+    async fn insert_request(sqlite: &SqlitePool, age: u64) {
+        sqlx::query("INSERT INTO request(timestamp, is_alarm) VALUES ($1, true)")
+            .bind(ModelRequest::now_i64() - i64::try_from(age).unwrap())
+            .execute(sqlite)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    // Requests are removed by age first, and then by row count
+    async fn maintenance_prune() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.request_retention_days = 2;
+        app_envs.request_retention_rows = 3;
+        for days in [5, 3, 1, 1, 1, 0, 0] {
+            insert_request(&sqlite, days * ONE_DAY_AS_SEC + 10).await;
+        }
+
+        let result = Maintenance::new(&app_envs, &sqlite).prune().await.unwrap();
+
+        assert_eq!(result, 4);
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(
+            result.iter().map(|i| i.request_id).collect::<Vec<_>>(),
+            [5, 6, 7]
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // The row count is exceeded, but every request is from the last day, and still counts towards the rate limits
+    async fn maintenance_prune_last_day() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.request_retention_rows = 1;
+        for age in [ONE_DAY_AS_SEC + 10, ONE_DAY_AS_SEC - 10, 60 * 60, 10] {
+            insert_request(&sqlite, age).await;
+        }
+
+        let result = Maintenance::new(&app_envs, &sqlite).prune().await.unwrap();
+
+        assert_eq!(result, 1);
+        let result = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(
+            result.iter().map(|i| i.request_id).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    async fn maintenance_run_once() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        insert_request(&sqlite, 0).await;
+        let maintenance = Maintenance::new(&app_envs, &sqlite);
+
+        assert!(maintenance.run_once(false).await.is_ok());
+        assert!(maintenance.run_once(true).await.is_ok());
+        assert_eq!(ModelRequest::test_get_all(&sqlite).await.unwrap().len(), 1);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A scripted response, returned by the mock server for a single request
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+    delay: Duration,
+}
+
+impl Default for MockResponse {
+    /// A successful Pushover response
+    fn default() -> Self {
+        Self::new(200, r#"{"status":1,"request":"request"}"#)
+    }
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: body.to_owned(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Wait before writing the response, used to trigger client timeouts
+    pub const fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let headers = self
+            .headers
+            .iter()
+            .fold(String::new(), |mut output, (key, value)| {
+                let _ = write!(output, "{key}: {value}\r\n");
+                output
+            });
+        format!(
+            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n{headers}\r\n{}",
+            self.status,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+/// A request received by the mock server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path, including any query string
+    pub path: String,
+    pub body: String,
+}
+
+/// Minimal in-process HTTP/1.1 server, each connection handles a single request and is then closed.
+/// Scripted responses are used in order, once they run out every request gets `MockResponse::default()`
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    responses: Arc<Mutex<VecDeque<MockResponse>>>,
+}
+
+impl MockServer {
+    #[expect(clippy::unwrap_used)]
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            requests: Arc::new(Mutex::new(vec![])),
+            responses: Arc::new(Mutex::new(VecDeque::new())),
+        };
+        let inner = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(inner.clone().handle(stream));
+            }
+        });
+        server
+    }
+
+    /// Base url of the server, without a trailing slash
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Queue a response, to be returned for the next unanswered request
+    #[expect(clippy::unwrap_used)]
+    pub fn push(&self, response: MockResponse) {
+        self.responses.lock().unwrap().push_back(response);
+    }
+
+    /// Every request received so far, in order
+    #[expect(clippy::unwrap_used)]
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Read the request head, and then the body as given by content-length
+    async fn read_request(stream: &mut TcpStream) -> Option<MockRequest> {
+        let mut buf = vec![];
+        let mut chunk = [0u8; 1024];
+        let head_end = loop {
+            let read = stream.read(&mut chunk).await.ok()?;
+            if read == 0 {
+                return None;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+            if let Some(index) = buf.windows(4).position(|i| i == b"\r\n\r\n") {
+                break index + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_owned();
+        let path = request_line.next()?.to_owned();
+        let content_length = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or_default();
+        while buf.len() < head_end + content_length {
+            let read = stream.read(&mut chunk).await.ok()?;
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+        Some(MockRequest {
+            method,
+            path,
+            body: String::from_utf8_lossy(&buf[head_end..]).to_string(),
+        })
+    }
+
+    #[expect(clippy::unwrap_used)]
+    async fn handle(self, mut stream: TcpStream) {
+        let Some(request) = Self::read_request(&mut stream).await else {
+            return;
+        };
+        self.requests.lock().unwrap().push(request);
+        let response = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_default();
+        tokio::time::sleep(response.delay).await;
+        // The client may have already given up, in which case the write fails, which is expected
+        stream.write_all(&response.to_bytes()).await.ok();
+        stream.shutdown().await.ok();
+    }
+}
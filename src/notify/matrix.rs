@@ -0,0 +1,323 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{S, app_env::AppEnv, app_error::AppError, request::PushRequest};
+
+/// Alarm pushes after this index are sent with the escalated formatting
+const ESCALATE_AFTER: u8 = 10;
+
+/// Maximum number of attempts when the homeserver responds with a rate-limit
+const MAX_ATTEMPTS: u8 = 4;
+
+/// Used when a rate-limit response doesn't include a `retry_after_ms`
+const DEFAULT_RETRY_MS: u64 = 1000;
+
+/// Never wait longer than this between attempts, the alarm loop has its own schedule to keep
+const MAX_RETRY_MS: u64 = 10_000;
+
+/// Make sure each transaction id is unique, even if two messages are sent in the same millisecond
+static TXN_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// m.room.message event content
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct RoomMessage {
+    msgtype: &'static str,
+    body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted_body: Option<String>,
+}
+
+/// Standard Matrix error response
+#[derive(Debug, Deserialize)]
+struct MatrixError {
+    errcode: Option<String>,
+    error: Option<String>,
+    retry_after_ms: Option<u64>,
+}
+
+/// Post alarm messages into a Matrix room via the client-server API
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    access_token: String,
+    client: Client,
+    room_url: Url,
+}
+
+impl Matrix {
+    /// Only created if the access token, homeserver, and room id are all set
+    pub fn new(app_envs: &AppEnv) -> Option<Self> {
+        let access_token = app_envs.matrix_access_token.as_ref()?;
+        let homeserver = app_envs.matrix_homeserver.as_ref()?;
+        let room_id = app_envs.matrix_room_id.as_ref()?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(10))
+            .user_agent(format!(
+                "{}/{}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .ok()?;
+        Some(Self {
+            access_token: access_token.to_owned(),
+            client,
+            room_url: Self::gen_room_url(homeserver, room_id).ok()?,
+        })
+    }
+
+    /// Room ids contain `!` and `:`, so need to be correctly encoded as a path segment
+    fn gen_room_url(homeserver: &str, room_id: &str) -> Result<Url, AppError> {
+        let mut url = Url::parse(homeserver)?;
+        url.path_segments_mut()
+            .map_err(|()| AppError::Matrix(format!("invalid homeserver: {homeserver}")))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                room_id,
+                "send",
+                "m.room.message",
+            ]);
+        Ok(url)
+    }
+
+    /// Transaction ids only need to be unique per access token
+    fn gen_txn_id() -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        format!("{now}.{}", TXN_COUNT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Escalated alarm pushes are sent as a bold red `@room` mention, everything else is plain text
+    fn gen_message(push_request: &PushRequest, msg: &str) -> RoomMessage {
+        match push_request {
+            PushRequest::Alarm { push_index, .. } if *push_index > ESCALATE_AFTER => RoomMessage {
+                msgtype: "m.text",
+                body: format!("@room {msg} - {push_index}"),
+                format: Some("org.matrix.custom.html"),
+                formatted_body: Some(format!(
+                    "@room <strong><font color=\"#ff0000\">{} - {push_index}</font></strong>",
+                    Self::escape_html(msg)
+                )),
+            },
+            PushRequest::Alarm { push_index, .. } => RoomMessage {
+                msgtype: "m.text",
+                body: format!("{msg} - {push_index}"),
+                format: None,
+                formatted_body: None,
+            },
+            PushRequest::TestRequest => RoomMessage {
+                msgtype: "m.notice",
+                body: msg.to_owned(),
+                format: None,
+                formatted_body: None,
+            },
+        }
+    }
+
+    fn escape_html(input: &str) -> String {
+        input
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// How long to wait before the next attempt, capped at MAX_RETRY_MS
+    fn retry_after(error: &MatrixError) -> Duration {
+        Duration::from_millis(
+            error
+                .retry_after_ms
+                .unwrap_or(DEFAULT_RETRY_MS)
+                .min(MAX_RETRY_MS),
+        )
+    }
+
+    /// Send a message into the room, retrying on rate-limit responses
+    pub async fn send(&self, push_request: &PushRequest, msg: &str) -> Result<(), AppError> {
+        let message = Self::gen_message(push_request, msg);
+        // Re-use the same transaction id for each attempt, so the homeserver can de-duplicate
+        let url = format!("{}/{}", self.room_url, Self::gen_txn_id());
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let response = self
+                .client
+                .put(&url)
+                .bearer_auth(&self.access_token)
+                .json(&message)
+                .send()
+                .await?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            let error = response.json::<MatrixError>().await.unwrap_or(MatrixError {
+                errcode: None,
+                error: None,
+                retry_after_ms: None,
+            });
+            if status == StatusCode::TOO_MANY_REQUESTS && attempt < MAX_ATTEMPTS {
+                let wait = Self::retry_after(&error);
+                tracing::debug!("matrix rate-limited, attempt {attempt}, waiting {wait:?}");
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            return Err(AppError::Matrix(format!(
+                "{status} {} {}",
+                error.errcode.unwrap_or_default(),
+                error.error.unwrap_or_default()
+            )));
+        }
+        Err(AppError::Matrix(S!("rate-limited")))
+    }
+}
+
+/// notify_matrix
+///
+/// cargo watch -q -c -w src/ -x 'test notify_matrix -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        mock_server::{MockResponse, MockServer},
+        tests::gen_app_envs,
+    };
+
+    #[test]
+    fn notify_matrix_new() {
+        let mut app_envs = gen_app_envs(Uuid::new_v4());
+        assert!(Matrix::new(&app_envs).is_none());
+
+        app_envs.matrix_access_token = Some(S!("token"));
+        app_envs.matrix_homeserver = Some(S!("https://matrix.example.com/"));
+        assert!(Matrix::new(&app_envs).is_none());
+
+        app_envs.matrix_room_id = Some(S!("!abc:example.com"));
+        let result = Matrix::new(&app_envs).unwrap();
+        assert_eq!(
+            result.room_url.as_str(),
+            "https://matrix.example.com/_matrix/client/v3/rooms/!abc:example.com/send/m.room.message"
+        );
+
+        app_envs.matrix_homeserver = Some(S!("not a url"));
+        assert!(Matrix::new(&app_envs).is_none());
+    }
+
+    #[test]
+    fn notify_matrix_room_url_encoded() {
+        let result = Matrix::gen_room_url("https://matrix.example.com", "!a/b?c:example.com");
+        assert_eq!(
+            result.unwrap().as_str(),
+            "https://matrix.example.com/_matrix/client/v3/rooms/!a%2Fb%3Fc:example.com/send/m.room.message"
+        );
+    }
+
+    #[test]
+    fn notify_matrix_txn_id_unique() {
+        let first = Matrix::gen_txn_id();
+        let second = Matrix::gen_txn_id();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn notify_matrix_gen_message() {
+        let result = Matrix::gen_message(&PushRequest::test_alarm(1), "wake <up>");
+        assert_eq!(result.msgtype, "m.text");
+        assert_eq!(result.body, "wake <up> - 1");
+        assert!(result.formatted_body.is_none());
+
+        let result = Matrix::gen_message(&PushRequest::test_alarm(ESCALATE_AFTER), "wake");
+        assert!(result.formatted_body.is_none());
+
+        let result = Matrix::gen_message(
+            &PushRequest::Alarm {
+                alarm_id: 1,
+                push_index: ESCALATE_AFTER + 1,
+            },
+            "wake <up>",
+        );
+        assert_eq!(result.body, "@room wake <up> - 11");
+        assert_eq!(result.format, Some("org.matrix.custom.html"));
+        assert_eq!(
+            result.formatted_body.unwrap(),
+            "@room <strong><font color=\"#ff0000\">wake &lt;up&gt; - 11</font></strong>"
+        );
+
+        let result = Matrix::gen_message(&PushRequest::TestRequest, "test");
+        assert_eq!(result.msgtype, "m.notice");
+        assert_eq!(result.body, "test");
+    }
+
+    #[test]
+    fn notify_matrix_retry_after() {
+        let error = serde_json::from_str::<MatrixError>(
+            r#"{"errcode":"M_LIMIT_EXCEEDED","error":"Too many requests","retry_after_ms":2000}"#,
+        )
+        .unwrap();
+        assert_eq!(Matrix::retry_after(&error), Duration::from_secs(2));
+
+        let error = serde_json::from_str::<MatrixError>(
+            r#"{"errcode":"M_LIMIT_EXCEEDED","retry_after_ms":600000}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Matrix::retry_after(&error),
+            Duration::from_millis(MAX_RETRY_MS)
+        );
+
+        let error =
+            serde_json::from_str::<MatrixError>(r#"{"errcode":"M_LIMIT_EXCEEDED"}"#).unwrap();
+        assert_eq!(
+            Matrix::retry_after(&error),
+            Duration::from_millis(DEFAULT_RETRY_MS)
+        );
+    }
+
+    #[tokio::test]
+    // A rate-limited send is retried once the advertised delay has passed, with the same transaction id
+    async fn notify_matrix_send_rate_limited() {
+        let server = MockServer::start().await;
+        let mut app_envs = gen_app_envs(Uuid::new_v4());
+        app_envs.matrix_access_token = Some(S!("token"));
+        app_envs.matrix_homeserver = Some(server.url());
+        app_envs.matrix_room_id = Some(S!("!abc:example.com"));
+        let matrix = Matrix::new(&app_envs).unwrap();
+        server.push(MockResponse::new(
+            429,
+            r#"{"errcode":"M_LIMIT_EXCEEDED","error":"Too many requests","retry_after_ms":300}"#,
+        ));
+        server.push(MockResponse::new(200, r#"{"event_id":"$event"}"#));
+
+        let start = std::time::Instant::now();
+        let result = matrix.send(&PushRequest::test_alarm(1), "wake").await;
+
+        assert!(result.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].path, requests[1].path);
+        assert!(
+            requests[0]
+                .path
+                .starts_with("/_matrix/client/v3/rooms/!abc:example.com/send/m.room.message/")
+        );
+        assert_eq!(requests[0].body, requests[1].body);
+    }
+}
@@ -0,0 +1,477 @@
+mod matrix;
+mod telegram;
+
+use std::{fmt, str::FromStr, time::Instant};
+
+pub use matrix::Matrix;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+pub use telegram::Telegram;
+
+use crate::{
+    C,
+    app_env::AppEnv,
+    app_error::AppError,
+    db::{ModelOutbox, ModelRequest},
+    request::PushRequest,
+    ws_messages::PushOptions,
+};
+
+/// Every channel an alarm push can be sent through
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Pushover,
+    Telegram,
+    Matrix,
+}
+
+impl Channel {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pushover => "pushover",
+            Self::Telegram => "telegram",
+            Self::Matrix => "matrix",
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Channel {
+    type Err = AppError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pushover" => Ok(Self::Pushover),
+            "telegram" => Ok(Self::Telegram),
+            "matrix" => Ok(Self::Matrix),
+            _ => Err(AppError::ChainInvalid(s.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<String> for Channel {
+    type Error = AppError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+/// Send alarm pushes through the configured chain of channels, in order, until one of them succeeds
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    app_envs: AppEnv,
+    /// Channels which have returned a fatal error, and so are skipped for the rest of this Notifier's life
+    disabled: Vec<Channel>,
+    matrix: Option<Matrix>,
+    sqlite: SqlitePool,
+    telegram: Option<Telegram>,
+}
+
+impl Notifier {
+    pub fn new(app_envs: &AppEnv, sqlite: &SqlitePool) -> Self {
+        Self {
+            app_envs: C!(app_envs),
+            disabled: vec![],
+            matrix: Matrix::new(app_envs),
+            sqlite: C!(sqlite),
+            telegram: Telegram::new(app_envs),
+        }
+    }
+
+    /// Telegram & Matrix only have a single destination, so the request is recorded, and limited, against an empty recipient
+    async fn send_other(
+        &self,
+        channel: Channel,
+        push_request: &PushRequest,
+        msg: &str,
+    ) -> Result<(), AppError> {
+        let requests_made =
+            ModelRequest::count_recent(&self.sqlite, push_request, channel, "").await?;
+        self.app_envs
+            .limit(channel, push_request)
+            .check(requests_made)?;
+        let request = ModelRequest::insert(&self.sqlite, push_request, channel, "", msg).await?;
+        let start = Instant::now();
+        let result = self.send_other_request(channel, push_request, msg).await;
+        match &result {
+            Ok(()) => {
+                ModelRequest::set_delivered(&self.sqlite, request.request_id, start.elapsed())
+                    .await?;
+            }
+            Err(e) => {
+                ModelRequest::set_error(
+                    &self.sqlite,
+                    request.request_id,
+                    &e.detail(),
+                    start.elapsed(),
+                )
+                .await?;
+            }
+        }
+        result
+    }
+
+    async fn send_other_request(
+        &self,
+        channel: Channel,
+        push_request: &PushRequest,
+        msg: &str,
+    ) -> Result<(), AppError> {
+        match (channel, push_request) {
+            (Channel::Telegram, PushRequest::Alarm { push_index, .. }) => {
+                let telegram = self
+                    .telegram
+                    .as_ref()
+                    .ok_or_else(|| AppError::ChainInvalid(channel.to_string()))?;
+                telegram.send_alarm(msg, *push_index).await?;
+            }
+            (Channel::Matrix, _) => {
+                let matrix = self
+                    .matrix
+                    .as_ref()
+                    .ok_or_else(|| AppError::ChainInvalid(channel.to_string()))?;
+                matrix.send(push_request, msg).await?;
+            }
+            _ => return Err(AppError::ChainInvalid(channel.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Send via a single channel, any Pushover outbox entries left pending are added to `pending`
+    async fn send_channel(
+        &self,
+        channel: Channel,
+        push_request: &PushRequest,
+        msg: &str,
+        options: &PushOptions,
+        recipients: &[String],
+        pending: &mut Vec<i64>,
+    ) -> Result<(), AppError> {
+        match channel {
+            Channel::Pushover => {
+                push_request
+                    .make_request_outbox(
+                        &self.app_envs,
+                        &self.sqlite,
+                        msg,
+                        options,
+                        recipients,
+                        pending,
+                    )
+                    .await
+            }
+            Channel::Telegram | Channel::Matrix => {
+                self.send_other(channel, push_request, msg).await
+            }
+        }
+    }
+
+    /// True if every channel in the chain has been disabled, so no push can ever be delivered
+    pub fn is_disabled(&self) -> bool {
+        self.app_envs
+            .notify_chain
+            .iter()
+            .all(|channel| self.disabled.contains(channel))
+    }
+
+    /// Once the push has been delivered, any Pushover outbox entries for it would only be duplicates
+    async fn clear_outbox(&self, pending: &[i64]) {
+        for outbox_id in pending {
+            if let Err(e) = ModelOutbox::delete(&self.sqlite, *outbox_id).await {
+                tracing::error!("outbox_id {outbox_id}: {e}");
+            }
+        }
+    }
+
+    /// Try each channel in the chain, in order, returning the channel that delivered the push.
+    /// If every channel fails, the errors from each channel are combined into a single `AppError::NotifyFailed`,
+    /// and any Pushover outbox entries are left for the outbox worker.
+    /// A channel that returns a fatal error is disabled, and skipped on any later sends
+    pub async fn send(
+        &mut self,
+        push_request: &PushRequest,
+        msg: &str,
+        options: &PushOptions,
+        recipients: &[String],
+    ) -> Result<Channel, AppError> {
+        let mut errors = vec![];
+        let mut pending = vec![];
+        for channel in C!(self.app_envs.notify_chain) {
+            if self.disabled.contains(&channel) {
+                errors.push(format!("{channel}: disabled"));
+                continue;
+            }
+            match self
+                .send_channel(
+                    channel,
+                    push_request,
+                    msg,
+                    options,
+                    recipients,
+                    &mut pending,
+                )
+                .await
+            {
+                Ok(()) => {
+                    tracing::debug!("delivered via {channel}");
+                    self.clear_outbox(&pending).await;
+                    return Ok(channel);
+                }
+                Err(e) => {
+                    tracing::warn!("{channel}: {e}");
+                    if e.is_fatal() {
+                        tracing::error!("{channel} disabled");
+                        self.disabled.push(channel);
+                    }
+                    errors.push(format!("{channel}: {e}"));
+                }
+            }
+        }
+        Err(AppError::NotifyFailed(errors.join(", ")))
+    }
+}
+
+/// notify_mod
+///
+/// cargo watch -q -c -w src/ -x 'test notify_mod -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_server::{MockResponse, MockServer},
+        tests::{test_cleanup, test_setup},
+    };
+
+    #[test]
+    fn notify_mod_channel_from_str() {
+        assert_eq!(Channel::from_str("pushover").unwrap(), Channel::Pushover);
+        assert_eq!(Channel::from_str(" Telegram ").unwrap(), Channel::Telegram);
+        assert_eq!(Channel::from_str("MATRIX").unwrap(), Channel::Matrix);
+        assert!(Channel::from_str("email").is_err());
+
+        for channel in [Channel::Pushover, Channel::Telegram, Channel::Matrix] {
+            assert_eq!(Channel::from_str(channel.as_str()).unwrap(), channel);
+        }
+    }
+
+    #[tokio::test]
+    // Pushover is the first channel in the chain, and delivers the push
+    async fn notify_mod_send_primary() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), Channel::Pushover);
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].channel, Channel::Pushover);
+        assert!(requests[0].delivered);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Telegram is first in the chain, but fails, so is retried via pushover
+    async fn notify_mod_send_failover() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.notify_chain = vec![Channel::Telegram, Channel::Pushover];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), Channel::Pushover);
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].channel, Channel::Telegram);
+        assert!(!requests[0].delivered);
+        assert_eq!(requests[1].channel, Channel::Pushover);
+        assert!(requests[1].delivered);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Every channel fails, each attempt is recorded, and none are marked as delivered
+    async fn notify_mod_send_all_failed() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.notify_chain = vec![Channel::Pushover, Channel::Telegram];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[crate::S!("unknown")],
+            )
+            .await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "All notification channels failed: pushover: Invalid recipient: 'unknown', telegram: Reqwest Error"
+        );
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].channel, Channel::Telegram);
+        assert!(!requests[0].delivered);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // A fatal error disables the channel, any later sends skip it
+    async fn notify_mod_send_disabled() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.notify_chain = vec![Channel::Matrix, Channel::Telegram];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+        assert!(!notifier.is_disabled());
+
+        notifier.disabled.push(Channel::Matrix);
+        assert!(!notifier.is_disabled());
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "All notification channels failed: matrix: disabled, telegram: Reqwest Error"
+        );
+
+        notifier.disabled.push(Channel::Telegram);
+        assert!(notifier.is_disabled());
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].channel, Channel::Telegram);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Telegram is rate-limited, so no request is made, and pushover is used instead
+    async fn notify_mod_send_limited() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.notify_chain = vec![Channel::Telegram, Channel::Pushover];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        app_envs.limits.insert(
+            Channel::Telegram,
+            crate::app_env::ChannelLimits {
+                alarm: crate::app_env::RateLimit { hour: 0, day: 0 },
+                ..Default::default()
+            },
+        );
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), Channel::Pushover);
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].channel, Channel::Pushover);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Pushover fails in a retryable way, Telegram delivers the push, so nothing is left in the outbox to be replayed later
+    async fn notify_mod_send_failover_outbox() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        let pushover = MockServer::start().await;
+        for _ in 0..3 {
+            pushover.push(MockResponse::new(503, r#"{"status":0}"#));
+        }
+        let telegram = MockServer::start().await;
+        telegram.push(MockResponse::new(
+            200,
+            r#"{"ok":true,"result":{"message_id":1}}"#,
+        ));
+        app_envs.pushover_url = pushover.url();
+        app_envs.notify_chain = vec![Channel::Pushover, Channel::Telegram];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        app_envs.telegram_url = telegram.url();
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), Channel::Telegram);
+        assert_eq!(pushover.requests().len(), 3);
+        assert_eq!(telegram.requests().len(), 1);
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Every channel fails, so the Pushover outbox entry is kept for the outbox worker
+    async fn notify_mod_send_failed_outbox() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        let pushover = MockServer::start().await;
+        for _ in 0..3 {
+            pushover.push(MockResponse::new(503, r#"{"status":0}"#));
+        }
+        app_envs.pushover_url = pushover.url();
+        app_envs.notify_chain = vec![Channel::Pushover, Channel::Telegram];
+        app_envs.telegram_token = Some(crate::S!("token"));
+        app_envs.telegram_chat_id = Some(crate::S!("1234"));
+        let mut notifier = Notifier::new(&app_envs, &sqlite);
+
+        let result = notifier
+            .send(
+                &PushRequest::test_alarm(1),
+                "msg",
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+
+        assert!(result.is_err());
+        let result = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].attempts, 1);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
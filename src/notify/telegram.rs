@@ -0,0 +1,350 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::sync::mpsc::Sender;
+
+use crate::{alarm_schedule::CronMessage, app_env::AppEnv, app_error::AppError};
+
+/// callback_data attached to the inline "Dismiss" button
+const DISMISS: &str = "dismiss";
+
+/// How long, in seconds, Telegram should hold a getUpdates request open
+const POLL_TIMEOUT: u64 = 30;
+
+/// Delay before trying to poll again after a failed getUpdates request
+const POLL_ERROR_WAIT: Duration = Duration::from_secs(10);
+
+/// Generic wrapper around every Bot API response
+#[derive(Debug, Deserialize)]
+struct BotResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    id: String,
+    data: Option<String>,
+    message: Option<CallbackMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackMessage {
+    chat: Chat,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+    username: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetUpdates {
+    offset: i64,
+    timeout: u64,
+    allowed_updates: [&'static str; 1],
+}
+
+/// Send alarm messages via the Telegram Bot API, and listen for the inline "Dismiss" button being pressed
+#[derive(Debug, Clone)]
+pub struct Telegram {
+    chat_id: String,
+    client: Client,
+    url: String,
+}
+
+impl Telegram {
+    /// Only created if both `TELEGRAM_TOKEN` and `TELEGRAM_CHAT_ID` are set
+    pub fn new(app_envs: &AppEnv) -> Option<Self> {
+        let token = app_envs.telegram_token.as_ref()?;
+        let chat_id = app_envs.telegram_chat_id.as_ref()?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+            .user_agent(format!(
+                "{}/{}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .ok()?;
+        Some(Self {
+            chat_id: chat_id.to_owned(),
+            client,
+            url: format!("{}/bot{token}", app_envs.telegram_url.trim_end_matches('/')),
+        })
+    }
+
+    /// POST a json body to a given Bot API method, and unwrap the `result`
+    async fn post<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: &(impl Serialize + Sync),
+    ) -> Result<T, AppError> {
+        let response = self
+            .client
+            .post(format!("{}/{method}", self.url))
+            .json(body)
+            .send()
+            .await?
+            .json::<BotResponse<T>>()
+            .await?;
+        match (response.ok, response.result) {
+            (true, Some(result)) => Ok(result),
+            _ => Err(AppError::Telegram(
+                response
+                    .description
+                    .unwrap_or_else(|| format!("{method} failed")),
+            )),
+        }
+    }
+
+    /// Generate the sendMessage body, with an inline "Dismiss" button
+    fn gen_alarm_body(&self, msg: &str, index: u8) -> serde_json::Value {
+        json!({
+            "chat_id": self.chat_id,
+            "text": format!("{msg} - {index}"),
+            "reply_markup": {
+                "inline_keyboard": [[{ "text": "Dismiss", "callback_data": DISMISS }]]
+            }
+        })
+    }
+
+    /// Send a single alarm push
+    pub async fn send_alarm(&self, msg: &str, index: u8) -> Result<(), AppError> {
+        self.post::<serde_json::Value>("sendMessage", &self.gen_alarm_body(msg, index))
+            .await?;
+        Ok(())
+    }
+
+    /// Only accept button presses from the configured chat, `chat_id` can either be the numeric id or an @username
+    fn is_dismiss(&self, callback_query: &CallbackQuery) -> bool {
+        callback_query.data.as_deref() == Some(DISMISS)
+            && callback_query.message.as_ref().is_some_and(|message| {
+                message.chat.id.to_string() == self.chat_id
+                    || message
+                        .chat
+                        .username
+                        .as_ref()
+                        .is_some_and(|username| format!("@{username}") == self.chat_id)
+            })
+    }
+
+    /// Long-poll getUpdates, and convert any "Dismiss" button press into a `CronMessage::AlarmDismiss`
+    async fn poll(self, sx: Sender<CronMessage>) {
+        let mut offset = 0;
+        loop {
+            let body = GetUpdates {
+                offset,
+                timeout: POLL_TIMEOUT,
+                allowed_updates: ["callback_query"],
+            };
+            match self.post::<Vec<Update>>("getUpdates", &body).await {
+                Ok(updates) => {
+                    for update in updates {
+                        offset = offset.max(update.update_id + 1);
+                        let Some(callback_query) = update.callback_query else {
+                            continue;
+                        };
+                        if self.is_dismiss(&callback_query) {
+                            tracing::debug!("telegram dismiss");
+                            sx.send(CronMessage::AlarmDismiss).await.ok();
+                        }
+                        let answer = json!({ "callback_query_id": callback_query.id });
+                        if let Err(e) = self
+                            .post::<serde_json::Value>("answerCallbackQuery", &answer)
+                            .await
+                        {
+                            tracing::error!("{e}");
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("{e}");
+                    tokio::time::sleep(POLL_ERROR_WAIT).await;
+                }
+            }
+        }
+    }
+
+    /// Spawn the getUpdates long-polling loop onto its own tokio thread
+    pub fn spawn_poll(self, sx: Sender<CronMessage>) {
+        tokio::spawn(self.poll(sx));
+    }
+}
+
+/// notify_telegram
+///
+/// cargo watch -q -c -w src/ -x 'test notify_telegram -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        S,
+        mock_server::{MockResponse, MockServer},
+        tests::gen_app_envs,
+    };
+
+    fn gen_telegram(chat_id: &str) -> Telegram {
+        let mut app_envs = gen_app_envs(Uuid::new_v4());
+        app_envs.telegram_token = Some(S!("test_token"));
+        app_envs.telegram_chat_id = Some(S!(chat_id));
+        Telegram::new(&app_envs).unwrap()
+    }
+
+    /// A Telegram client pointed at a dedicated mock server
+    async fn mock_telegram() -> (Telegram, MockServer) {
+        let server = MockServer::start().await;
+        let mut app_envs = gen_app_envs(Uuid::new_v4());
+        app_envs.telegram_token = Some(S!("test_token"));
+        app_envs.telegram_chat_id = Some(S!("1234"));
+        app_envs.telegram_url = server.url();
+        (Telegram::new(&app_envs).unwrap(), server)
+    }
+
+    fn gen_callback_query(json: &str) -> CallbackQuery {
+        serde_json::from_str::<Update>(json)
+            .unwrap()
+            .callback_query
+            .unwrap()
+    }
+
+    #[test]
+    fn notify_telegram_new() {
+        let mut app_envs = gen_app_envs(Uuid::new_v4());
+        assert!(Telegram::new(&app_envs).is_none());
+
+        app_envs.telegram_token = Some(S!("test_token"));
+        assert!(Telegram::new(&app_envs).is_none());
+
+        app_envs.telegram_chat_id = Some(S!("1234"));
+        app_envs.telegram_url = S!("http://127.0.0.1:8080/");
+        let result = Telegram::new(&app_envs).unwrap();
+        assert_eq!(result.url, "http://127.0.0.1:8080/bottest_token");
+        assert_eq!(result.chat_id, "1234");
+    }
+
+    #[test]
+    fn notify_telegram_alarm_body() {
+        let telegram = gen_telegram("1234");
+
+        let result = telegram.gen_alarm_body("message", 4);
+
+        assert_eq!(result["chat_id"], "1234");
+        assert_eq!(result["text"], "message - 4");
+        assert_eq!(
+            result["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            DISMISS
+        );
+    }
+
+    #[test]
+    fn notify_telegram_is_dismiss() {
+        let telegram = gen_telegram("1234");
+
+        let result = gen_callback_query(
+            r#"{"update_id":1,"callback_query":{"id":"a","data":"dismiss","message":{"chat":{"id":1234}}}}"#,
+        );
+        assert!(telegram.is_dismiss(&result));
+
+        // Wrong chat
+        let result = gen_callback_query(
+            r#"{"update_id":1,"callback_query":{"id":"a","data":"dismiss","message":{"chat":{"id":4321}}}}"#,
+        );
+        assert!(!telegram.is_dismiss(&result));
+
+        // Wrong data
+        let result = gen_callback_query(
+            r#"{"update_id":1,"callback_query":{"id":"a","data":"other","message":{"chat":{"id":1234}}}}"#,
+        );
+        assert!(!telegram.is_dismiss(&result));
+
+        // No message
+        let result =
+            gen_callback_query(r#"{"update_id":1,"callback_query":{"id":"a","data":"dismiss"}}"#);
+        assert!(!telegram.is_dismiss(&result));
+
+        // Chat id as a username
+        let telegram = gen_telegram("@alarm_chat");
+        let result = gen_callback_query(
+            r#"{"update_id":1,"callback_query":{"id":"a","data":"dismiss","message":{"chat":{"id":1234,"username":"alarm_chat"}}}}"#,
+        );
+        assert!(telegram.is_dismiss(&result));
+    }
+
+    #[tokio::test]
+    async fn notify_telegram_send_alarm() {
+        let (telegram, server) = mock_telegram().await;
+        server.push(MockResponse::new(
+            200,
+            r#"{"ok":true,"result":{"message_id":1}}"#,
+        ));
+
+        let result = telegram.send_alarm("message", 3).await;
+
+        assert!(result.is_ok());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/bottest_token/sendMessage");
+        let body = serde_json::from_str::<serde_json::Value>(&requests[0].body).unwrap();
+        assert_eq!(body["chat_id"], "1234");
+        assert_eq!(body["text"], "message - 3");
+    }
+
+    #[tokio::test]
+    async fn notify_telegram_send_alarm_err() {
+        let (telegram, server) = mock_telegram().await;
+        server.push(MockResponse::new(
+            403,
+            r#"{"ok":false,"description":"Forbidden: bot was blocked by the user"}"#,
+        ));
+
+        let result = telegram.send_alarm("message", 3).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Telegram: Forbidden: bot was blocked by the user"
+        );
+    }
+
+    #[tokio::test]
+    // A dismiss button press, from the configured chat, is forwarded to the AlarmSchedule, and then answered
+    async fn notify_telegram_poll_dismiss() {
+        let (telegram, server) = mock_telegram().await;
+        server.push(MockResponse::new(
+            200,
+            r#"{"ok":true,"result":[{"update_id":7,"callback_query":{"id":"a","data":"dismiss","message":{"chat":{"id":1234}}}}]}"#,
+        ));
+        server.push(MockResponse::new(200, r#"{"ok":true,"result":true}"#));
+        let (sx, mut rx) = tokio::sync::mpsc::channel(8);
+
+        telegram.spawn_poll(sx);
+
+        let result = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap();
+        assert_eq!(result, Some(CronMessage::AlarmDismiss));
+        crate::sleep!(100);
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/bottest_token/getUpdates");
+        assert_eq!(requests[1].path, "/bottest_token/answerCallbackQuery");
+        assert!(requests[1].body.contains(r#""callback_query_id":"a""#));
+        // The next poll starts after the last update
+        let body = serde_json::from_str::<serde_json::Value>(&requests[2].body).unwrap();
+        assert_eq!(body["offset"], 8);
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+
+use crate::{
+    C,
+    app_env::AppEnv,
+    app_error::AppError,
+    db::{ModelOutbox, ModelSetting},
+    request::PushRequest,
+};
+
+/// How often to check the outbox for undelivered pushes
+const DRAIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Retry pushes which failed due to connectivity issues, dropping any that are too old to be useful
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    app_envs: AppEnv,
+    sqlite: SqlitePool,
+}
+
+impl Outbox {
+    pub fn new(app_envs: &AppEnv, sqlite: &SqlitePool) -> Self {
+        Self {
+            app_envs: C!(app_envs),
+            sqlite: C!(sqlite),
+        }
+    }
+
+    /// Attempt each pending entry, oldest first, including any written before a crash or restart.
+    /// Stops early if an attempt fails in a retryable way, as connectivity probably still hasn't returned
+    async fn drain(&self) -> Result<(), AppError> {
+        let expired =
+            ModelOutbox::delete_expired(&self.sqlite, self.app_envs.outbox_max_age).await?;
+        if expired > 0 {
+            tracing::debug!("{expired} expired outbox entries removed");
+        }
+
+        let app_envs = ModelSetting::apply(&self.sqlite, &self.app_envs).await?;
+        let in_flight = PushRequest::send_window(&self.app_envs).as_secs() + 1;
+        let pending =
+            ModelOutbox::get_pending(&self.sqlite, self.app_envs.outbox_max_age, in_flight).await?;
+        for entry in pending {
+            let Some(recipient) = app_envs
+                .recipients
+                .iter()
+                .find(|i| i.name == entry.recipient)
+            else {
+                tracing::warn!("unknown recipient, removing {entry}");
+                ModelOutbox::delete(&self.sqlite, entry.outbox_id).await?;
+                continue;
+            };
+            let result = entry
+                .push_request()
+                .send_with_retry(
+                    &app_envs,
+                    &self.sqlite,
+                    &entry.message,
+                    &entry.push_options(),
+                    recipient,
+                )
+                .await;
+            ModelOutbox::settle(&self.sqlite, entry.outbox_id, &result).await?;
+            match result {
+                Ok(()) => tracing::debug!("outbox delivered {entry}"),
+                Err(e) if e.is_retryable() => {
+                    tracing::debug!("outbox still failing: {e}");
+                    break;
+                }
+                Err(e) => tracing::error!("outbox dropped {entry}: {e}"),
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self) {
+        loop {
+            tokio::time::sleep(DRAIN_INTERVAL).await;
+            if let Err(e) = self.drain().await {
+                tracing::error!("{e}");
+            }
+        }
+    }
+
+    /// Spawn the outbox worker onto its own tokio thread
+    pub fn spawn(self) {
+        tokio::spawn(self.run());
+    }
+}
+
+/// outbox
+///
+/// cargo watch -q -c -w src/ -x 'test outbox_ -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_env::DEFAULT_RECIPIENT,
+        db::ModelRequest,
+        tests::{test_cleanup, test_setup},
+        ws_messages::PushOptions,
+    };
+
+    async fn insert_pending(sqlite: &SqlitePool, recipient: &str, push_request: &PushRequest) {
+        let entry = ModelOutbox::insert(
+            sqlite,
+            push_request,
+            recipient,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+        ModelOutbox::set_failed(sqlite, entry.outbox_id, "error")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    // Pending entries are delivered, logged against the alarm that was ringing, and removed from the outbox
+    async fn outbox_drain_delivered() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        insert_pending(
+            &sqlite,
+            DEFAULT_RECIPIENT,
+            &PushRequest::Alarm {
+                alarm_id: 5,
+                push_index: 3,
+            },
+        )
+        .await;
+        // Not yet attempted, and could still be in flight, so ignored
+        ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::test_alarm(4),
+            DEFAULT_RECIPIENT,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        Outbox::new(&app_envs, &sqlite).drain().await.unwrap();
+
+        let result = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].push_index, Some(4));
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].is_alarm);
+        assert_eq!(requests[0].alarm_id, Some(5));
+        assert_eq!(requests[0].push_index, Some(3));
+        assert!(requests[0].delivered);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // An entry written just before a crash has no failed attempt, it's still delivered once it can't be in flight
+    async fn outbox_drain_unattempted() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        ModelOutbox::insert(
+            &sqlite,
+            &PushRequest::test_alarm(2),
+            DEFAULT_RECIPIENT,
+            "message",
+            &PushOptions::default(),
+        )
+        .await
+        .unwrap();
+        sqlx::query("UPDATE outbox SET timestamp = timestamp - $1")
+            .bind(i64::try_from(PushRequest::send_window(&app_envs).as_secs()).unwrap() + 2)
+            .execute(&sqlite)
+            .await
+            .unwrap();
+
+        Outbox::new(&app_envs, &sqlite).drain().await.unwrap();
+
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].delivered);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Expired entries, and entries for unknown recipients, are removed without a request being made
+    async fn outbox_drain_removed() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        insert_pending(&sqlite, DEFAULT_RECIPIENT, &PushRequest::test_alarm(1)).await;
+        sqlx::query("UPDATE outbox SET timestamp = 0")
+            .execute(&sqlite)
+            .await
+            .unwrap();
+        insert_pending(&sqlite, "unknown", &PushRequest::TestRequest).await;
+
+        Outbox::new(&app_envs, &sqlite).drain().await.unwrap();
+
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        assert!(
+            ModelRequest::test_get_all(&sqlite)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
@@ -1,40 +1,186 @@
-use reqwest::Client;
+use reqwest::{Client, StatusCode, header::HeaderMap};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
 use url::Url;
 
-use crate::{C, app_env::AppEnv, app_error::AppError, db::ModelRequest};
+use crate::{
+    C, S,
+    app_env::{AppEnv, Recipient},
+    app_error::{AppError, PushoverError},
+    backoff::Backoff,
+    db::{ModelOutbox, ModelRequest, ModelSetting},
+    notify::Channel,
+    ws_messages::PushOptions,
+};
 
-/// Pushover api url
-const URL: &str = "https://api.pushover.net/1/messages.json";
+/// Path of the messages endpoint, relative to the configurable api base url
+const MESSAGES_PATH: &str = "1/messages.json";
 
-type Params<'a> = [(&'a str, String); 4];
+/// Maximum number of attempts for a single push to a single recipient
+const MAX_ATTEMPTS: u32 = 3;
 
-#[derive(Debug, Serialize, Deserialize)]
-/// Response from pushover api, currently not actually doing anything with it
-struct PostRequest {
-    status: usize,
-    request: String,
+/// Delay between attempts, kept short as alarm pushes are sent every 25 seconds
+const RETRY: Backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(4));
+
+type Params<'a> = Vec<(&'a str, String)>;
+
+/// Json body of a response from the pushover api, `token` & `user` are set to "invalid" when rejected
+#[derive(Debug, Default, Deserialize)]
+struct PostBody {
+    status: i64,
+    request: Option<String>,
+    #[serde(default)]
+    errors: Vec<String>,
+    token: Option<String>,
+    user: Option<String>,
+}
+
+/// Everything captured from a pushover api response, stored alongside the request row
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PostResponse {
+    pub http_status: u16,
+    pub status: i64,
+    pub request: Option<String>,
+    pub errors: Vec<String>,
+    pub limit_remaining: Option<i64>,
+    pub limit_reset: Option<i64>,
+    invalid_token: bool,
+    invalid_user: bool,
+}
+
+impl PostResponse {
+    /// Parse a single numeric header
+    fn parse_header(headers: &HeaderMap, name: &str) -> Option<i64> {
+        headers
+            .get(name)
+            .and_then(|i| i.to_str().ok())
+            .and_then(|i| i.trim().parse().ok())
+    }
+
+    /// Build from the raw parts of a response, a body that isn't valid json is recorded as an error rather than discarded
+    pub fn from_parts(http_status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let body = serde_json::from_slice::<PostBody>(body).unwrap_or_else(|e| PostBody {
+            errors: vec![format!("invalid response body: {e}")],
+            ..PostBody::default()
+        });
+        Self {
+            http_status: http_status.as_u16(),
+            status: body.status,
+            request: body.request,
+            errors: body.errors,
+            limit_remaining: Self::parse_header(headers, "X-Limit-App-Remaining"),
+            limit_reset: Self::parse_header(headers, "X-Limit-App-Reset"),
+            invalid_token: body.token.as_deref() == Some("invalid"),
+            invalid_user: body.user.as_deref() == Some("invalid"),
+        }
+    }
+
+    /// The combined errors array, as stored in the database
+    pub fn errors_joined(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join(", "))
+        }
+    }
+
+    /// Only a 2xx response with a status of 1 counts as delivered
+    pub const fn is_delivered(&self) -> bool {
+        self.status == 1 && self.http_status >= 200 && self.http_status < 300
+    }
+
+    /// Convert an unsuccessful response into a typed error
+    pub fn check(&self) -> Result<(), PushoverError> {
+        let errors = self.errors_joined().unwrap_or_default();
+        if self.is_delivered() {
+            Ok(())
+        } else if self.invalid_token {
+            Err(PushoverError::InvalidToken(errors))
+        } else if self.invalid_user {
+            Err(PushoverError::InvalidUser(errors))
+        } else if self.http_status == StatusCode::TOO_MANY_REQUESTS.as_u16() {
+            Err(PushoverError::RateLimited(self.limit_reset))
+        } else if self.http_status >= 500 {
+            Err(PushoverError::Server(self.http_status))
+        } else {
+            Err(PushoverError::Rejected {
+                status: self.http_status,
+                errors,
+            })
+        }
+    }
 }
 
 pub enum PushRequest {
-    Alarm(u8),
+    /// A push of the ringing alarm, the id is of the alarm that started ringing, which may since have been changed or deleted
+    Alarm {
+        alarm_id: i64,
+        push_index: u8,
+    },
     TestRequest,
 }
 
+/// The type of a PushRequest, without the push index, the request table derives it from `is_alarm`
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+    Alarm,
+    TestRequest,
+}
+
+impl RequestKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Alarm => "alarm",
+            Self::TestRequest => "test_request",
+        }
+    }
+}
+
+impl TryFrom<String> for RequestKind {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "alarm" => Ok(Self::Alarm),
+            "test_request" => Ok(Self::TestRequest),
+            _ => Err(format!("invalid request kind: {value}")),
+        }
+    }
+}
+
 impl PushRequest {
-    /// How many requests can be made in the previous hour
-    const fn hour_limit(&self) -> i64 {
+    pub const fn push_index(&self) -> Option<u8> {
+        match self {
+            Self::Alarm { push_index, .. } => Some(*push_index),
+            Self::TestRequest => None,
+        }
+    }
+
+    pub const fn alarm_id(&self) -> Option<i64> {
         match self {
-            Self::Alarm(_) => 60,
-            Self::TestRequest => 10,
+            Self::Alarm { alarm_id, .. } => Some(*alarm_id),
+            Self::TestRequest => None,
+        }
+    }
+
+    #[cfg(test)]
+    pub const fn test_alarm(push_index: u8) -> Self {
+        Self::Alarm {
+            alarm_id: 1,
+            push_index,
         }
     }
 
     /// Get the reqwest client, in reality should never actually fail
-    fn get_client() -> Result<Client, AppError> {
+    pub fn get_client(app_envs: &AppEnv) -> Result<Client, AppError> {
+        let timeout = Duration::from_millis(app_envs.pushover_timeout_ms);
         Ok(reqwest::Client::builder()
-            .connect_timeout(std::time::Duration::from_millis(5000))
+            .connect_timeout(timeout.min(Duration::from_secs(5)))
+            .timeout(timeout)
             .gzip(true)
             .brotli(true)
             .user_agent(format!(
@@ -45,85 +191,232 @@ impl PushRequest {
             .build()?)
     }
 
-    #[cfg(not(debug_assertions))]
-    /// The actual request via PushOver api
-    async fn send_request(url: Url) -> Result<PostRequest, AppError> {
-        let client = Self::get_client()?;
-        Ok(client.post(url).send().await?.json::<PostRequest>().await?)
+    /// The longest a push to a single recipient can take, every attempt timing out, plus the delay between each
+    pub fn send_window(app_envs: &AppEnv) -> Duration {
+        let timeout = Duration::from_millis(app_envs.pushover_timeout_ms);
+        (1..MAX_ATTEMPTS).fold(timeout * MAX_ATTEMPTS, |total, attempt| {
+            total + RETRY.ceiling(attempt)
+        })
     }
 
-    #[cfg(debug_assertions)]
-    #[expect(clippy::unused_async)]
-    async fn send_request(url: Url) -> Result<PostRequest, AppError> {
-        use tracing::info;
-
-        use crate::S;
+    /// The full messages endpoint url, `PUSHOVER_URL` may or may not have a trailing slash
+    fn messages_url(app_envs: &AppEnv) -> String {
+        format!(
+            "{}/{MESSAGES_PATH}",
+            app_envs.pushover_url.trim_end_matches('/')
+        )
+    }
 
-        let _client = Self::get_client()?;
-        info!("sending request");
-        info!("{url:?}");
-        Ok(PostRequest {
-            status: 1,
-            request: S!("request"),
-        })
+    /// The actual request via PushOver api
+    async fn send_request(app_envs: &AppEnv, url: Url) -> Result<PostResponse, AppError> {
+        let client = Self::get_client(app_envs)?;
+        let response = client.post(url).send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        Ok(PostResponse::from_parts(status, &headers, &body))
     }
 
     const fn get_priority<'a>(&self) -> &'a str {
         match self {
-            Self::Alarm(_) => "1",
+            Self::Alarm { .. } => "1",
             Self::TestRequest => "0",
         }
     }
 
-    /// Generate the params, aka the message
-    fn gen_params<'a>(&self, app_envs: &AppEnv, msg: &str) -> Params<'a> {
-        let mut params = [
+    /// Generate the params, aka the message, for a single recipient
+    fn gen_params<'a>(
+        &self,
+        app_envs: &AppEnv,
+        recipient: &Recipient,
+        msg: &str,
+        options: &PushOptions,
+    ) -> Params<'a> {
+        let mut params = vec![
             ("token", C!(app_envs.token_app)),
-            ("user", C!(app_envs.token_user)),
+            ("user", C!(recipient.user)),
             ("message", String::new()),
             ("priority", self.get_priority().to_owned()),
         ];
 
         match self {
-            Self::Alarm(index) => {
-                params[2].1 = format!("{msg} - {index}");
+            Self::Alarm { push_index, .. } => {
+                params[2].1 = format!("{msg} - {push_index}");
             }
             Self::TestRequest => {
                 params[2].1 = msg.to_string();
             }
         }
+        if !recipient.devices.is_empty() {
+            params.push(("device", recipient.devices.join(",")));
+        }
+        Self::push_options(&mut params, options);
         params
     }
 
+    /// Append any of the optional message parameters that have been set
+    fn push_options(params: &mut Params, options: &PushOptions) {
+        let optional = [
+            ("title", C!(options.title)),
+            ("sound", C!(options.sound)),
+            ("url", C!(options.url)),
+            ("url_title", C!(options.url_title)),
+            ("html", options.html.then(|| S!("1"))),
+            ("monospace", options.monospace.then(|| S!("1"))),
+            ("ttl", options.ttl.map(|i| i.to_string())),
+            ("timestamp", options.timestamp.map(|i| i.to_string())),
+        ];
+        params.extend(
+            optional
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|value| (key, value))),
+        );
+    }
+
     /// Insert a new request into the database
-    async fn insert_request(&self, sqlite: &SqlitePool) -> Result<(), AppError> {
-        ModelRequest::insert(sqlite, self).await?;
-        Ok(())
+    async fn insert_request(
+        &self,
+        sqlite: &SqlitePool,
+        recipient: &str,
+        msg: &str,
+    ) -> Result<ModelRequest, AppError> {
+        ModelRequest::insert(sqlite, self, Channel::Pushover, recipient, msg).await
+    }
+
+    /// A single attempt, will check to make sure that haven't made too many request to the recipient in the previous hour, or day.
+    /// Every attempt is recorded, so retries count against the limit
+    async fn attempt_request(
+        &self,
+        app_envs: &AppEnv,
+        sqlite: &SqlitePool,
+        url: &Url,
+        recipient: &str,
+        msg: &str,
+    ) -> Result<(), AppError> {
+        let requests_made =
+            ModelRequest::count_recent(sqlite, self, Channel::Pushover, recipient).await?;
+        app_envs
+            .limit(Channel::Pushover, self)
+            .check(requests_made)?;
+        tracing::debug!("Sending request to {recipient}");
+        let request = self.insert_request(sqlite, recipient, msg).await?;
+        let start = Instant::now();
+        match Self::send_request(app_envs, C!(url)).await {
+            Ok(response) => {
+                tracing::debug!("Request sent");
+                ModelRequest::set_response(sqlite, request.request_id, &response, start.elapsed())
+                    .await?;
+                Ok(response.check()?)
+            }
+            Err(e) => {
+                ModelRequest::set_error(sqlite, request.request_id, &e.detail(), start.elapsed())
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Make the request to a single recipient, retrying transient failures with a capped exponential backoff
+    pub async fn send_with_retry(
+        &self,
+        app_envs: &AppEnv,
+        sqlite: &SqlitePool,
+        msg: &str,
+        options: &PushOptions,
+        recipient: &Recipient,
+    ) -> Result<(), AppError> {
+        let params = self.gen_params(app_envs, recipient, msg, options);
+        let url = reqwest::Url::parse_with_params(&Self::messages_url(app_envs), &params)?;
+        let mut attempt = 1;
+        loop {
+            match self
+                .attempt_request(app_envs, sqlite, &url, &recipient.name, msg)
+                .await
+            {
+                Err(e) if e.is_retryable() && attempt < MAX_ATTEMPTS => {
+                    let delay = RETRY.delay(attempt);
+                    tracing::warn!(
+                        "{}: attempt {attempt} failed, retrying in {delay:?}: {e}",
+                        recipient.name
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
-    /// Make the request, will check to make sure that haven't made too many request in previous hour
-    /// get_ip functions are recursive, to deal with no network at first boot
+    /// Write the push to the outbox before making the request, if it still fails after every retry it's left there for the outbox worker,
+    /// and its id added to `pending`
+    async fn make_recipient_request(
+        &self,
+        app_envs: &AppEnv,
+        sqlite: &SqlitePool,
+        msg: &str,
+        options: &PushOptions,
+        recipient: &Recipient,
+        pending: &mut Vec<i64>,
+    ) -> Result<(), AppError> {
+        let outbox = ModelOutbox::insert(sqlite, self, &recipient.name, msg, options).await?;
+        let result = self
+            .send_with_retry(app_envs, sqlite, msg, options, recipient)
+            .await;
+        if ModelOutbox::settle(sqlite, outbox.outbox_id, &result).await? {
+            pending.push(outbox.outbox_id);
+        }
+        result
+    }
+
+    /// Make the request to each of the targeted recipients, any failed pushes are left in the outbox
     pub async fn make_request(
         &self,
         app_envs: &AppEnv,
         sqlite: &SqlitePool,
         msg: &str,
+        options: &PushOptions,
+        targets: &[String],
     ) -> Result<(), AppError> {
-        let requests_made = ModelRequest::count_past_hour(sqlite, self).await?;
+        self.make_request_outbox(app_envs, sqlite, msg, options, targets, &mut vec![])
+            .await
+    }
 
-        if requests_made >= self.hour_limit() {
-            Err(AppError::TooManyRequests(requests_made))
-        } else {
-            tracing::debug!("Sending request");
-            let params = self.gen_params(app_envs, msg);
-            let url = reqwest::Url::parse_with_params(URL, &params)?;
-            self.insert_request(sqlite).await?;
-
-            Self::send_request(url).await?;
-            // do something with the response here?
-            tracing::debug!("Request sent");
-            Ok(())
+    /// Make the request to each of the targeted recipients, an empty `targets` means every recipient.
+    /// Any credentials stored at runtime take precedence over the env values.
+    /// A failure for one recipient doesn't stop the others, the first error is returned.
+    /// The ids of any outbox entries left pending are added to `pending`, so that a caller which delivers the push some other way can remove them
+    pub async fn make_request_outbox(
+        &self,
+        app_envs: &AppEnv,
+        sqlite: &SqlitePool,
+        msg: &str,
+        options: &PushOptions,
+        targets: &[String],
+        pending: &mut Vec<i64>,
+    ) -> Result<(), AppError> {
+        let app_envs = &ModelSetting::apply(sqlite, app_envs).await?;
+        let recipients = app_envs.get_recipients(targets);
+        if recipients.is_empty() {
+            return Err(AppError::RecipientInvalid(targets.join(",")));
         }
+        let mut output = Ok(());
+        for recipient in recipients {
+            if let Err(e) = self
+                .make_recipient_request(app_envs, sqlite, msg, options, recipient, pending)
+                .await
+            {
+                // Every other recipient would be rejected in exactly the same way
+                if e.is_fatal() {
+                    return Err(e);
+                }
+                if output.is_ok() {
+                    output = Err(e);
+                } else {
+                    tracing::error!("{}: {e}", recipient.name);
+                }
+            }
+        }
+        output
     }
 }
 
@@ -134,23 +427,49 @@ mod tests {
     use super::*;
     use crate::{
         S,
+        app_env::DEFAULT_RECIPIENT,
+        mock_server::{MockResponse, MockServer},
         tests::{test_cleanup, test_setup},
     };
 
+    /// Setup with a dedicated mock server, so that responses can be scripted
+    async fn mock_setup() -> (AppEnv, SqlitePool, uuid::Uuid, MockServer) {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        let server = MockServer::start().await;
+        app_envs.pushover_url = server.url();
+        (app_envs, sqlite, uuid, server)
+    }
+
+    async fn mock_test_request(app_envs: &AppEnv, sqlite: &SqlitePool) -> Result<(), AppError> {
+        PushRequest::TestRequest
+            .make_request(app_envs, sqlite, "msg", &PushOptions::default(), &[])
+            .await
+    }
+
     #[tokio::test]
     async fn test_request_generate_params() {
         let (app_envs, sqlite, uuid) = test_setup().await;
 
-        let push_request = PushRequest::Alarm(0);
-        let result = push_request.gen_params(&app_envs, &uuid.to_string());
+        let push_request = PushRequest::test_alarm(0);
+        let result = push_request.gen_params(
+            &app_envs,
+            &app_envs.recipients[0],
+            &uuid.to_string(),
+            &PushOptions::default(),
+        );
 
         assert_eq!(result[0], ("token", S!("test_token_app")));
         assert_eq!(result[2], ("message", format!("{uuid} - 0")));
         assert_eq!(result[1], ("user", S!("test_token_user")));
         assert_eq!(result[3], ("priority", S!("1")));
 
-        let push_request = PushRequest::Alarm(8);
-        let result = push_request.gen_params(&app_envs, &uuid.to_string());
+        let push_request = PushRequest::test_alarm(8);
+        let result = push_request.gen_params(
+            &app_envs,
+            &app_envs.recipients[0],
+            &uuid.to_string(),
+            &PushOptions::default(),
+        );
 
         assert_eq!(result[0], ("token", S!("test_token_app")));
         assert_eq!(result[2], ("message", format!("{uuid} - 8")));
@@ -158,12 +477,255 @@ mod tests {
         assert_eq!(result[3], ("priority", S!("1")));
 
         let push_request = PushRequest::TestRequest;
-        let result = push_request.gen_params(&app_envs, &uuid.to_string());
+        let result = push_request.gen_params(
+            &app_envs,
+            &app_envs.recipients[0],
+            &uuid.to_string(),
+            &PushOptions::default(),
+        );
 
         assert_eq!(result[0], ("token", S!("test_token_app")));
         assert_eq!(result[2], ("message", uuid.to_string()));
         assert_eq!(result[1], ("user", S!("test_token_user")));
         assert_eq!(result[3], ("priority", S!("0")));
+        assert_eq!(result.len(), 4);
+
+        let recipient = Recipient {
+            name: S!("jack"),
+            user: S!("user_jack"),
+            devices: vec![S!("phone"), S!("watch")],
+        };
+        let result = push_request.gen_params(
+            &app_envs,
+            &recipient,
+            &uuid.to_string(),
+            &PushOptions::default(),
+        );
+        assert_eq!(result[1], ("user", S!("user_jack")));
+        assert_eq!(result[4], ("device", S!("phone,watch")));
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[test]
+    fn test_request_generate_params_options() {
+        let app_envs = crate::tests::gen_app_envs(uuid::Uuid::new_v4());
+        let options = PushOptions {
+            title: Some(S!("title")),
+            sound: Some(S!("siren")),
+            url: Some(S!("https://www.example.com")),
+            url_title: Some(S!("example")),
+            html: true,
+            monospace: false,
+            ttl: Some(60),
+            timestamp: Some(1_700_000_000),
+        };
+
+        let result = PushRequest::test_alarm(1).gen_params(
+            &app_envs,
+            &app_envs.recipients[0],
+            "msg",
+            &options,
+        );
+
+        assert_eq!(result.len(), 11);
+        assert_eq!(
+            result[4..],
+            [
+                ("title", S!("title")),
+                ("sound", S!("siren")),
+                ("url", S!("https://www.example.com")),
+                ("url_title", S!("example")),
+                ("html", S!("1")),
+                ("ttl", S!("60")),
+                ("timestamp", S!("1700000000")),
+            ]
+        );
+
+        let options = PushOptions {
+            monospace: true,
+            ..PushOptions::default()
+        };
+        let result = PushRequest::TestRequest.gen_params(
+            &app_envs,
+            &app_envs.recipients[0],
+            "msg",
+            &options,
+        );
+        assert_eq!(result.len(), 5);
+        assert_eq!(result[4], ("monospace", S!("1")));
+    }
+
+    fn gen_response(
+        http_status: u16,
+        headers: &[(&'static str, &str)],
+        body: &str,
+    ) -> PostResponse {
+        let mut header_map = HeaderMap::new();
+        for (key, value) in headers {
+            header_map.insert(*key, value.parse().unwrap());
+        }
+        PostResponse::from_parts(
+            StatusCode::from_u16(http_status).unwrap(),
+            &header_map,
+            body.as_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_request_response_ok() {
+        let result = gen_response(
+            200,
+            &[
+                ("X-Limit-App-Limit", "10000"),
+                ("X-Limit-App-Remaining", "7496"),
+                ("X-Limit-App-Reset", "1393653600"),
+            ],
+            r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#,
+        );
+
+        assert!(result.is_delivered());
+        assert!(result.check().is_ok());
+        assert_eq!(result.http_status, 200);
+        assert_eq!(
+            result.request.as_deref(),
+            Some("647d2300-702c-4b38-8b2f-d56326ae460b")
+        );
+        assert_eq!(result.limit_remaining, Some(7496));
+        assert_eq!(result.limit_reset, Some(1_393_653_600));
+        assert!(result.errors_joined().is_none());
+    }
+
+    #[test]
+    fn test_request_response_err() {
+        let result = gen_response(
+            400,
+            &[],
+            r#"{"token":"invalid","errors":["application token is invalid"],"status":0,"request":"5042853c"}"#,
+        );
+        assert_eq!(
+            result.check(),
+            Err(PushoverError::InvalidToken(S!(
+                "application token is invalid"
+            )))
+        );
+        assert!(AppError::from(result.check().unwrap_err()).is_fatal());
+        assert_eq!(result.request.as_deref(), Some("5042853c"));
+
+        let result = gen_response(
+            400,
+            &[],
+            r#"{"user":"invalid","errors":["user identifier is invalid"],"status":0,"request":"5042853c"}"#,
+        );
+        assert_eq!(
+            result.check(),
+            Err(PushoverError::InvalidUser(S!("user identifier is invalid")))
+        );
+        assert!(!AppError::from(result.check().unwrap_err()).is_fatal());
+
+        let result = gen_response(
+            400,
+            &[],
+            r#"{"message":"cannot be blank","errors":["message cannot be blank","title is too long"],"status":0,"request":"5042853c"}"#,
+        );
+        assert_eq!(
+            result.check(),
+            Err(PushoverError::Rejected {
+                status: 400,
+                errors: S!("message cannot be blank, title is too long")
+            })
+        );
+
+        let result = gen_response(
+            429,
+            &[
+                ("X-Limit-App-Remaining", "0"),
+                ("X-Limit-App-Reset", "1393653600"),
+            ],
+            r#"{"errors":["application over limit"],"status":0}"#,
+        );
+        assert_eq!(
+            result.check(),
+            Err(PushoverError::RateLimited(Some(1_393_653_600)))
+        );
+        assert_eq!(result.limit_remaining, Some(0));
+
+        // Non-json body
+        let result = gen_response(502, &[], "<html>Bad Gateway</html>");
+        assert_eq!(result.check(), Err(PushoverError::Server(502)));
+        assert!(result.errors[0].starts_with("invalid response body"));
+
+        // 200, but status isn't 1
+        let result = gen_response(200, &[], r#"{"status":0}"#);
+        assert!(!result.is_delivered());
+        assert!(result.check().is_err());
+    }
+
+    #[tokio::test]
+    // Request made to each recipient, with the hour limit counted per recipient
+    async fn test_request_make_request_recipients() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.recipients.push(Recipient {
+            name: S!("jack"),
+            user: S!("user_jack"),
+            devices: vec![],
+        });
+
+        for _ in 1..=10 {
+            ModelRequest::insert(
+                &sqlite,
+                &PushRequest::TestRequest,
+                Channel::Pushover,
+                DEFAULT_RECIPIENT,
+                "",
+            )
+            .await
+            .unwrap();
+        }
+
+        // default recipient is over the limit, but jack still receives the request
+        let result = PushRequest::TestRequest
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Too many requests made in the past hour: 10"
+        );
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 11);
+        assert_eq!(requests[10].recipient, "jack");
+
+        let result = PushRequest::TestRequest
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[S!("jack")],
+            )
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(ModelRequest::test_get_all(&sqlite).await.unwrap().len(), 12);
+
+        let result = PushRequest::TestRequest
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[S!("sam")],
+            )
+            .await;
+        assert_eq!(result.unwrap_err().to_string(), "Invalid recipient: 'sam'");
+
+        // Delivered, and over the limit, pushes aren't left in the outbox
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
 
         test_cleanup(uuid, Some(sqlite)).await;
     }
@@ -186,8 +748,14 @@ mod tests {
         assert!(request_len.is_ok());
         assert_eq!(request_len.unwrap().len(), 60);
 
-        let result = PushRequest::Alarm(0)
-            .make_request(&app_envs, &sqlite, &uuid.to_string())
+        let result = PushRequest::test_alarm(0)
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
             .await;
 
         assert!(result.is_err());
@@ -217,8 +785,14 @@ mod tests {
         assert!(request_len.is_ok());
         assert_eq!(request_len.unwrap().len(), 60);
 
-        let result = PushRequest::Alarm(0)
-            .make_request(&app_envs, &sqlite, &uuid.to_string())
+        let result = PushRequest::test_alarm(0)
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
             .await;
         assert!(result.is_ok());
 
@@ -249,7 +823,13 @@ mod tests {
         assert_eq!(request_len.unwrap().len(), 10);
 
         let result = PushRequest::TestRequest
-            .make_request(&app_envs, &sqlite, &uuid.to_string())
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
             .await;
 
         assert!(result.is_err());
@@ -279,7 +859,13 @@ mod tests {
         assert_eq!(request_len.unwrap().len(), 10);
 
         let result = PushRequest::TestRequest
-            .make_request(&app_envs, &sqlite, &uuid.to_string())
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
             .await;
         assert!(result.is_ok());
 
@@ -299,8 +885,14 @@ mod tests {
         assert!(request_len.is_ok());
         assert_eq!(request_len.unwrap().len(), 0);
 
-        let result = PushRequest::Alarm(0)
-            .make_request(&app_envs, &sqlite, &uuid.to_string())
+        let result = PushRequest::test_alarm(0)
+            .make_request(
+                &app_envs,
+                &sqlite,
+                &uuid.to_string(),
+                &PushOptions::default(),
+                &[],
+            )
             .await;
 
         assert!(result.is_ok());
@@ -311,4 +903,188 @@ mod tests {
 
         test_cleanup(uuid, Some(sqlite)).await;
     }
+
+    #[tokio::test]
+    // Successful response, the provider request id & rate-limit headers are stored
+    async fn test_request_mock_success() {
+        let (mut app_envs, sqlite, uuid, server) = mock_setup().await;
+        app_envs.pushover_url = format!("{}/", server.url());
+        server.push(
+            MockResponse::new(200, r#"{"status":1,"request":"abc-123"}"#)
+                .header("X-Limit-App-Limit", "10000")
+                .header("X-Limit-App-Remaining", "7496")
+                .header("X-Limit-App-Reset", "1393653600"),
+        );
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        assert!(result.is_ok());
+
+        let received = server.requests();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].method, "POST");
+        assert!(received[0].path.starts_with("/1/messages.json?"));
+        assert!(received[0].path.contains("token=test_token_app"));
+        assert!(received[0].path.contains("user=test_token_user"));
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].delivered);
+        assert_eq!(requests[0].http_status, Some(200));
+        assert_eq!(requests[0].api_status, Some(1));
+        assert_eq!(requests[0].provider_request_id, Some(S!("abc-123")));
+        assert_eq!(requests[0].limit_remaining, Some(7496));
+        assert_eq!(requests[0].limit_reset, Some(1_393_653_600));
+        assert!(requests[0].latency_ms.is_some());
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // 4xx responses aren't retried, and the errors array is stored
+    async fn test_request_mock_client_error() {
+        let (app_envs, sqlite, uuid, server) = mock_setup().await;
+        server.push(MockResponse::new(
+            400,
+            r#"{"user":"invalid","errors":["user identifier is invalid"],"status":0,"request":"def-456"}"#,
+        ));
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Pushover: invalid user: user identifier is invalid"
+        );
+        assert_eq!(server.requests().len(), 1);
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].delivered);
+        assert_eq!(requests[0].http_status, Some(400));
+        assert_eq!(requests[0].api_status, Some(0));
+        assert_eq!(requests[0].errors, Some(S!("user identifier is invalid")));
+
+        // Not retryable, so not left in the outbox
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // 5xx responses are retried, each attempt recorded, and left in the outbox once every attempt fails
+    async fn test_request_mock_server_error() {
+        let (app_envs, sqlite, uuid, server) = mock_setup().await;
+        for _ in 0..MAX_ATTEMPTS {
+            server.push(MockResponse::new(503, "<html>Service Unavailable</html>"));
+        }
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            AppError::Pushover(PushoverError::Server(503))
+        ));
+        assert_eq!(server.requests().len(), 3);
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert!(!request.delivered);
+            assert_eq!(request.http_status, Some(503));
+            assert!(request.errors.unwrap().starts_with("invalid response body"));
+        }
+        let outbox = ModelOutbox::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].attempts, 1);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // A 5xx followed by a success, only the final attempt is delivered
+    async fn test_request_mock_server_error_recovered() {
+        let (app_envs, sqlite, uuid, server) = mock_setup().await;
+        server.push(MockResponse::new(500, r#"{"status":0}"#));
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        assert!(result.is_ok());
+        assert_eq!(server.requests().len(), 2);
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].delivered);
+        assert!(requests[1].delivered);
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // 429, the reset header is returned in the error, and stored. The quota won't reset for a while, so it isn't retried
+    async fn test_request_mock_rate_limited() {
+        let (app_envs, sqlite, uuid, server) = mock_setup().await;
+        server.push(
+            MockResponse::new(429, r#"{"status":0,"errors":["application over quota"]}"#)
+                .header("X-Limit-App-Remaining", "0")
+                .header("X-Limit-App-Reset", "1393653600"),
+        );
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            AppError::Pushover(PushoverError::RateLimited(Some(1_393_653_600)))
+        ));
+
+        assert_eq!(server.requests().len(), 1);
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].http_status, Some(429));
+        assert_eq!(requests[0].limit_remaining, Some(0));
+        assert_eq!(requests[0].limit_reset, Some(1_393_653_600));
+        assert_eq!(requests[0].errors, Some(S!("application over quota")));
+        assert!(ModelOutbox::test_get_all(&sqlite).await.unwrap().is_empty());
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // The server doesn't respond within the timeout, each attempt is recorded as an error
+    async fn test_request_mock_timeout() {
+        let (mut app_envs, sqlite, uuid, server) = mock_setup().await;
+        app_envs.pushover_timeout_ms = 100;
+        for _ in 0..MAX_ATTEMPTS {
+            server.push(MockResponse::default().delay(Duration::from_millis(500)));
+        }
+
+        let result = mock_test_request(&app_envs, &sqlite).await;
+        let err = result.unwrap_err();
+        assert!(err.is_retryable());
+        assert!(matches!(err, AppError::Reqwest(ref e) if e.is_timeout()));
+        assert_eq!(server.requests().len(), 3);
+
+        let requests = ModelRequest::test_get_all(&sqlite).await.unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert!(!request.delivered);
+            assert!(request.http_status.is_none());
+            assert!(request.errors.is_some());
+        }
+        assert_eq!(ModelOutbox::test_get_all(&sqlite).await.unwrap().len(), 1);
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[tokio::test]
+    // Credentials stored at runtime are used instead of the env values
+    async fn test_request_mock_stored_credentials() {
+        let (app_envs, sqlite, uuid, server) = mock_setup().await;
+        ModelSetting::set_credentials(
+            &sqlite,
+            &crate::ws_messages::CredentialsUpdate {
+                token_app: Some(S!("stored_token")),
+                users: [(S!(DEFAULT_RECIPIENT), S!("stored_user"))].into(),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(mock_test_request(&app_envs, &sqlite).await.is_ok());
+
+        let received = server.requests();
+        assert!(received[0].path.contains("token=stored_token"));
+        assert!(received[0].path.contains("user=stored_user"));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
 }
@@ -1,7 +1,10 @@
 use super::WsStream;
 use crate::{app_env::AppEnv, app_error::AppError};
 use serde::{Deserialize, Serialize};
-use tokio_tungstenite::{self, connect_async, tungstenite::http::StatusCode};
+use tokio_tungstenite::{
+    self, connect_async,
+    tungstenite::{Error, http::StatusCode},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct PostRequest<'a> {
@@ -27,7 +30,7 @@ struct PostResponse {
 /// Make a https request to get an access token
 async fn get_auth_token(app_envs: &AppEnv) -> Result<String, AppError> {
     Ok(reqwest::Client::builder()
-        .connect_timeout(std::time::Duration::from_millis(5000))
+        .connect_timeout(std::time::Duration::from_secs(5))
         .gzip(true)
         .brotli(true)
         .user_agent(format!(
@@ -40,21 +43,44 @@ async fn get_auth_token(app_envs: &AppEnv) -> Result<String, AppError> {
         .json(&PostRequest::from(app_envs))
         .send()
         .await?
+        .error_for_status()?
         .json::<PostResponse>()
         .await?
         .response)
 }
 
-/// Connect to wesbsocket server
+/// A token request that was refused, or answered with something other than a token, is an auth failure.
+/// Connection errors, timeouts, and server errors are left as is
+async fn auth_token(app_envs: &AppEnv) -> Result<String, AppError> {
+    get_auth_token(app_envs).await.map_err(|e| match e {
+        AppError::Reqwest(ref r)
+            if r.is_decode() || r.status().is_some_and(|i| i.is_client_error()) =>
+        {
+            AppError::WsAuth(e.detail())
+        }
+        _ => e,
+    })
+}
+
+/// An upgrade refused because of the token is an auth failure, anything else is a connection failure
+fn upgrade_error(error: &Error) -> AppError {
+    match error {
+        Error::Http(response)
+            if matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) =>
+        {
+            AppError::WsAuth(response.status().to_string())
+        }
+        _ => AppError::TungsteniteConnect(error.to_string()),
+    }
+}
+
+/// Connect to wesbsocket server, auth failures are returned as `AppError::WsAuth`
 pub async fn ws_upgrade(app_envs: &AppEnv) -> Result<WsStream, AppError> {
-    let url = format!(
-        "{}/{}",
-        app_envs.ws_address,
-        get_auth_token(app_envs).await?
-    );
-    let (socket, response) = connect_async(url)
-        .await
-        .map_err(|i| AppError::TungsteniteConnect(i.to_string()))?;
+    let url = format!("{}/{}", app_envs.ws_address, auth_token(app_envs).await?);
+    let (socket, response) = connect_async(url).await.map_err(|i| upgrade_error(&i))?;
     match response.status() {
         StatusCode::SWITCHING_PROTOCOLS => Ok(socket),
         _ => Err(AppError::WsStatus),
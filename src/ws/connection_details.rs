@@ -1,59 +1,80 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
-use tokio::time::sleep;
+use tokio::{sync::watch, time::sleep};
 
-#[derive(Debug)]
-pub struct ConnectionDetails {
-    count: usize,
-    wait: Wait,
-    connection_instant: Option<Instant>,
-}
+use crate::{C, app_env::AppEnv, app_error::AppError, backoff::Backoff};
 
-#[derive(Debug)]
-enum Wait {
-    Short,
-    Long,
+/// Counters which persist across reconnects, included in the status response
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub struct ConnectionStats {
+    /// Unix timestamp, in seconds, of the next connection attempt, none while connected
+    pub next_retry: Option<u64>,
+    /// Consecutive failed connection attempts, while connected this is how many it took to connect
+    pub reconnect_attempts: u32,
+    /// Outgoing messages that couldn't be written, each one tears down the connection it was sent on
+    pub send_failures: u64,
+    /// Incoming frames that weren't json with a unique, so couldn't be responded to
+    pub unparseable: u64,
 }
 
-impl Wait {
-    const fn as_sec(&self) -> u8 {
-        match self {
-            Self::Short => 5,
-            Self::Long => 60,
-        }
-    }
+pub type StatsSender = watch::Sender<ConnectionStats>;
+
+#[derive(Debug)]
+pub struct ConnectionDetails {
+    attempts: u32,
+    auth: Backoff,
+    connect: Backoff,
+    connection_instant: Option<Instant>,
+    delay: Duration,
+    stats: StatsSender,
 }
 
 impl ConnectionDetails {
-    pub const fn new() -> Self {
+    pub fn new(app_envs: &AppEnv, stats: &StatsSender) -> Self {
         Self {
-            count: 0,
-            wait: Wait::Short,
+            attempts: 0,
+            auth: app_envs.ws_reconnect_auth,
+            connect: app_envs.ws_reconnect,
             connection_instant: None,
+            delay: Duration::ZERO,
+            stats: C!(stats),
         }
     }
 
-    /// increase attempt count, and set delay to long if 20+ attempts
-    /// Set is_connected to 0 and time to none
-    pub const fn fail_connect(&mut self) {
-        self.count += 1;
-        if self.count >= 20 {
-            self.wait = Wait::Long;
-        }
+    /// increase attempt count, and pick a delay, auth failures use their own, slower, backoff
+    pub fn fail_connect(&mut self, error: &AppError) {
+        self.attempts = self.attempts.saturating_add(1);
+        let backoff = if matches!(error, AppError::WsAuth(_)) {
+            self.auth
+        } else {
+            self.connect
+        };
+        self.delay = backoff.full_jitter(self.attempts);
+        let next_retry = jiff::Timestamp::now()
+            .checked_add(self.delay)
+            .ok()
+            .and_then(|i| u64::try_from(i.as_second()).ok());
+        self.stats.send_modify(|stats| {
+            stats.next_retry = next_retry;
+            stats.reconnect_attempts = self.attempts;
+        });
     }
 
-    /// delay the recconnect attempt by x seconds, depedning on ho wmany attempts already made
+    /// delay the reconnect attempt, by the delay picked on the last failure
     pub async fn reconnect_delay(&self) {
-        tracing::info!(self.count);
-        if self.count > 0 {
-            sleep(Duration::from_secs(u64::from(self.wait.as_sec()))).await;
+        tracing::info!(self.attempts, delay_ms = self.delay.as_millis());
+        if self.attempts > 0 {
+            sleep(self.delay).await;
         }
     }
 
-    /// called on each connect, to reset connection, count etc
+    /// called on each connect, to reset attempt count etc
     pub fn valid_connect(&mut self) {
-        self.wait = Wait::Short;
-        self.count = 0;
+        self.attempts = 0;
+        self.delay = Duration::ZERO;
         self.connection_instant = Some(Instant::now());
+        self.stats.send_modify(|stats| stats.next_retry = None);
         tracing::debug!(
             "{}",
             jiff::Zoned::now().timestamp().strftime("%Y-%m-%d %H:%M:%S")
@@ -64,3 +85,60 @@ impl ConnectionDetails {
         self.connection_instant.unwrap_or_else(Instant::now)
     }
 }
+
+/// connection_details
+///
+/// cargo watch -q -c -w src/ -x 'test connection_details -- --nocapture'
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{S, tests::gen_app_envs};
+
+    fn gen_details() -> (ConnectionDetails, StatsSender) {
+        let mut app_envs = gen_app_envs(uuid::Uuid::new_v4());
+        app_envs.ws_reconnect = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        app_envs.ws_reconnect_auth = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        let stats = StatsSender::new(ConnectionStats::default());
+        (ConnectionDetails::new(&app_envs, &stats), stats)
+    }
+
+    #[test]
+    // Connection failures back off up to the maximum, and are visible in stats
+    fn connection_details_fail_connect() {
+        let (mut details, stats) = gen_details();
+        for attempt in 1..=6 {
+            details.fail_connect(&AppError::TungsteniteConnect(S!("refused")));
+            assert!(details.delay <= details.connect.ceiling(attempt));
+            assert!(details.delay <= Duration::from_secs(1));
+            assert_eq!(stats.borrow().reconnect_attempts, attempt);
+            assert!(stats.borrow().next_retry.is_some());
+        }
+    }
+
+    #[test]
+    // Auth failures use their own backoff
+    fn connection_details_fail_auth() {
+        let (mut details, _stats) = gen_details();
+        let mut delays = vec![];
+        for _ in 0..20 {
+            details.attempts = 0;
+            details.fail_connect(&AppError::WsAuth(S!("401 Unauthorized")));
+            delays.push(details.delay);
+        }
+        assert!(delays.iter().all(|i| *i <= Duration::from_secs(10)));
+        assert!(delays.iter().any(|i| *i > Duration::from_secs(1)));
+    }
+
+    #[test]
+    // A valid connection resets the attempts, but keeps the count in stats
+    fn connection_details_valid_connect() {
+        let (mut details, stats) = gen_details();
+        details.fail_connect(&AppError::WsStatus);
+        details.fail_connect(&AppError::WsStatus);
+        details.valid_connect();
+        assert_eq!(details.attempts, 0);
+        assert_eq!(details.delay, Duration::ZERO);
+        assert_eq!(stats.borrow().reconnect_attempts, 2);
+        assert!(stats.borrow().next_retry.is_none());
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{Notify, broadcast::error::RecvError},
+    task::JoinHandle,
+};
+use tracing::{debug, error, warn};
+
+use crate::{C, alarm_schedule::EventSender, app_env::AppEnv, ws_messages::Response};
+
+use super::ws_sender::WSSender;
+
+/// Maximum number of events waiting to be sent, the oldest is dropped first
+const EVENT_QUEUE_LEN: usize = 128;
+
+/// A waiting event, the id identifies it even if the events ahead of it have been dropped
+#[derive(Debug)]
+struct QueuedEvent {
+    id: u64,
+    instant: Instant,
+    response: Response,
+}
+
+/// Events from the AlarmSchedule, kept until they can be sent, so that anything generated while disconnected is sent, in order, on reconnect
+#[derive(Debug, Clone)]
+pub struct EventQueue {
+    events: Arc<Mutex<VecDeque<QueuedEvent>>>,
+    max_age: Duration,
+    next_id: Arc<AtomicU64>,
+    notify: Arc<Notify>,
+}
+
+impl EventQueue {
+    fn new(max_age: Duration) -> Self {
+        Self {
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(EVENT_QUEUE_LEN))),
+            max_age,
+            next_id: Arc::new(AtomicU64::new(0)),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Subscribe to every AlarmSchedule event, for the lifetime of the program
+    pub fn spawn(app_envs: &AppEnv, events: &EventSender) -> Self {
+        let queue = Self::new(Duration::from_secs(app_envs.ws_event_max_age));
+        let mut event_rx = events.subscribe();
+        let output = C!(queue);
+        tokio::spawn(async move {
+            loop {
+                match event_rx.recv().await {
+                    Ok(response) => queue.push(response),
+                    Err(RecvError::Lagged(count)) => error!("events lagged: {count}"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        output
+    }
+
+    fn push(&self, response: Response) {
+        if let Ok(mut events) = self.events.lock() {
+            if events.len() >= EVENT_QUEUE_LEN {
+                events.pop_front();
+                warn!("event queue full, oldest event dropped");
+            }
+            events.push_back(QueuedEvent {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                instant: Instant::now(),
+                response,
+            });
+        }
+        self.notify.notify_one();
+    }
+
+    /// The oldest event that hasn't expired, left in the queue until `remove` is called, any expired events are dropped
+    fn peek(&self) -> Option<(u64, Response)> {
+        let mut events = self.events.lock().ok()?;
+        while events
+            .front()
+            .is_some_and(|i| i.instant.elapsed() > self.max_age)
+        {
+            events.pop_front();
+            debug!("expired event dropped");
+        }
+        events.front().map(|i| (i.id, C!(i.response)))
+    }
+
+    /// Remove a sent event, unless it has already been dropped to make room
+    fn remove(&self, id: u64) {
+        if let Ok(mut events) = self.events.lock()
+            && events.front().is_some_and(|i| i.id == id)
+        {
+            events.pop_front();
+        }
+    }
+
+    /// Send every waiting event to the connected client, and then any new events as they arrive, stops on the first failed send.
+    /// An event is only removed once sent, so one that's interrupted, by a failed send or the task being aborted, is the first sent on reconnect
+    pub fn forward(&self, ws_sender: &WSSender) -> JoinHandle<()> {
+        let queue = C!(self);
+        let ws_sender = C!(ws_sender);
+        tokio::spawn(async move {
+            loop {
+                while let Some((id, response)) = queue.peek() {
+                    if !ws_sender.send_event(response).await {
+                        return;
+                    }
+                    queue.remove(id);
+                }
+                queue.notify.notified().await;
+            }
+        })
+    }
+}
+
+/// event_queue
+///
+/// cargo watch -q -c -w src/ -x 'test event_queue -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        alarm_schedule::AlarmSchedule,
+        credentials::CredentialSender,
+        db::ModelAlarm,
+        tests::{test_cleanup, test_setup},
+    };
+
+    fn gen_event(push_index: u8) -> Response {
+        Response::AlarmDismissed { push_index }
+    }
+
+    /// Peek and then remove the oldest event, as a successful send would
+    fn pop_index(queue: &EventQueue) -> Option<u8> {
+        let (id, response) = queue.peek()?;
+        queue.remove(id);
+        match response {
+            Response::AlarmDismissed { push_index } => Some(push_index),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn event_queue_order() {
+        let queue = EventQueue::new(Duration::from_secs(60));
+        for i in 1..=3 {
+            queue.push(gen_event(i));
+        }
+        assert_eq!(pop_index(&queue), Some(1));
+        assert_eq!(pop_index(&queue), Some(2));
+        assert_eq!(pop_index(&queue), Some(3));
+        assert_eq!(pop_index(&queue), None);
+    }
+
+    #[test]
+    // The oldest events are dropped once the queue is full
+    fn event_queue_bounded() {
+        let queue = EventQueue::new(Duration::from_secs(60));
+        for i in 0..=u8::try_from(EVENT_QUEUE_LEN).unwrap_or(u8::MAX) {
+            queue.push(gen_event(i));
+        }
+        assert_eq!(
+            queue.events.lock().map(|i| i.len()).ok(),
+            Some(EVENT_QUEUE_LEN)
+        );
+        assert_eq!(pop_index(&queue), Some(1));
+    }
+
+    #[tokio::test]
+    async fn event_queue_expired() {
+        let queue = EventQueue::new(Duration::from_millis(50));
+        queue.push(gen_event(1));
+        crate::sleep!(100);
+        queue.push(gen_event(2));
+        assert_eq!(pop_index(&queue), Some(2));
+        assert_eq!(pop_index(&queue), None);
+    }
+
+    #[test]
+    // An event stays queued until removed, so one that failed to send, or was interrupted, is sent again
+    fn event_queue_peek() {
+        let queue = EventQueue::new(Duration::from_secs(60));
+        queue.push(gen_event(1));
+        queue.push(gen_event(2));
+        let (id, _) = queue.peek().unwrap();
+        assert_eq!(queue.peek().unwrap().0, id);
+        assert_eq!(pop_index(&queue), Some(1));
+        assert_eq!(pop_index(&queue), Some(2));
+        assert!(queue.peek().is_none());
+    }
+
+    #[test]
+    // Removing an event which was dropped, to make room, while it was being sent, leaves the next event queued
+    fn event_queue_remove_dropped() {
+        let queue = EventQueue::new(Duration::from_secs(60));
+        queue.push(gen_event(0));
+        let (id, _) = queue.peek().unwrap();
+        for i in 1..=u8::try_from(EVENT_QUEUE_LEN).unwrap_or(u8::MAX) {
+            queue.push(gen_event(i));
+        }
+        queue.remove(id);
+        assert_eq!(pop_index(&queue), Some(1));
+    }
+
+    #[tokio::test]
+    // Subscribed before the AlarmSchedule starts, the alarm scheduled at startup is kept until a client connects
+    async fn event_queue_spawn_startup() {
+        let (app_envs, sqlite, uuid) = test_setup().await;
+        ModelAlarm::add(&sqlite, (6, 15, None).into())
+            .await
+            .unwrap();
+        let (event_sx, _) = tokio::sync::broadcast::channel(32);
+        let queue = EventQueue::spawn(&app_envs, &event_sx);
+
+        AlarmSchedule::init(
+            C!(sqlite),
+            C!(app_envs),
+            event_sx,
+            &CredentialSender::new(None),
+        )
+        .await
+        .unwrap();
+        crate::sleep!(50);
+
+        let alarm = ModelAlarm::get(&sqlite).await.unwrap().unwrap();
+        assert!(matches!(
+            queue.peek(),
+            Some((_, Response::AlarmScheduled(i))) if i == alarm
+        ));
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+}
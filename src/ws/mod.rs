@@ -1,8 +1,11 @@
 mod connect;
 mod connection_details;
+mod event_queue;
 
 use connect::ws_upgrade;
 use connection_details::ConnectionDetails;
+pub use connection_details::{ConnectionStats, StatsSender};
+pub use event_queue::EventQueue;
 use futures_util::{
     StreamExt, TryStreamExt,
     lock::Mutex,
@@ -10,12 +13,17 @@ use futures_util::{
 };
 use sqlx::SqlitePool;
 use std::{sync::Arc, time::Duration};
-use tokio::{net::TcpStream, sync::mpsc::Sender, task::JoinHandle};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{Sender, error::TrySendError},
+    task::JoinHandle,
+};
 use tokio_tungstenite::{self, MaybeTlsStream, WebSocketStream, tungstenite::Message};
 use tracing::{error, info};
 
 use crate::{
-    C, alarm_schedule::CronMessage, app_env::AppEnv, app_error::AppError, ws::ws_sender::WSSender,
+    C, alarm_schedule::CronMessage, app_env::AppEnv, app_error::AppError,
+    credentials::CredentialSender, ws::ws_sender::WSSender,
 };
 
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
@@ -43,17 +51,40 @@ impl AutoClose {
     }
 }
 
-/// Handle each incoming ws message
-async fn incoming_ws_message(mut reader: WSReader, ws_sender: WSSender) {
+/// Process every queued command, in order, one at a time, so that responses are sent in the same order as the commands
+fn spawn_command_queue(ws_sender: &WSSender, depth: usize) -> (Sender<String>, JoinHandle<()>) {
+    let (queue, mut messages) = tokio::sync::mpsc::channel::<String>(depth);
+    let mut ws_sender = C!(ws_sender);
+    let handle = tokio::spawn(async move {
+        while let Some(message) = messages.recv().await {
+            ws_sender.on_text(message).await;
+        }
+    });
+    (queue, handle)
+}
+
+/// Handle each incoming ws message, commands are queued, and rejected if the queue is full, stops once any send fails
+async fn incoming_ws_message(mut reader: WSReader, ws_sender: WSSender, queue_depth: usize) {
     let mut auto_close = AutoClose::default();
     auto_close.init(&ws_sender);
-    while let Ok(Some(message)) = reader.try_next().await {
+    let (queue, commands) = spawn_command_queue(&ws_sender, queue_depth);
+    loop {
+        let message = tokio::select! {
+            message = reader.try_next() => message,
+            () = ws_sender.disconnected() => {
+                error!("send failed, closing connection");
+                ws_sender.close().await;
+                break;
+            }
+        };
+        let Ok(Some(message)) = message else {
+            break;
+        };
         match message {
             Message::Text(message) => {
-                let mut ws_sender = C!(ws_sender);
-                tokio::spawn(async move {
-                    ws_sender.on_text(message.to_string()).await;
-                });
+                if let Err(TrySendError::Full(message)) = queue.try_send(message.to_string()) {
+                    ws_sender.on_overflow(&message).await;
+                }
             }
             Message::Ping(_) => auto_close.init(&ws_sender),
             Message::Close(_) => {
@@ -63,6 +94,7 @@ async fn incoming_ws_message(mut reader: WSReader, ws_sender: WSSender) {
             _ => (),
         }
     }
+    commands.abort();
     info!("incoming_ws_message done");
 }
 
@@ -73,8 +105,11 @@ pub async fn open_connection(
     app_envs: AppEnv,
     sqlite: SqlitePool,
     sx: Sender<CronMessage>,
+    event_queue: EventQueue,
+    credentials: CredentialSender,
 ) -> Result<(), AppError> {
-    let mut connection_details = ConnectionDetails::new();
+    let stats = StatsSender::new(ConnectionStats::default());
+    let mut connection_details = ConnectionDetails::new(&app_envs, &stats);
     loop {
         info!("in connection loop, awaiting delay then try to connect");
         connection_details.reconnect_delay().await;
@@ -92,15 +127,20 @@ pub async fn open_connection(
                     &sqlite,
                     C!(sx),
                     Arc::new(Mutex::new(writer)),
+                    &credentials,
+                    &stats,
                 );
+                ws_sender.send_hello().await;
                 ws_sender.send_status().await;
-                incoming_ws_message(reader, ws_sender).await;
+                let events = event_queue.forward(&ws_sender);
+                incoming_ws_message(reader, ws_sender, app_envs.ws_queue_depth).await;
+                events.abort();
 
                 info!("aborted spawns, incoming_ws_message done, reconnect next");
             }
             Err(e) => {
                 error!("connection::{e}");
-                connection_details.fail_connect();
+                connection_details.fail_connect(&e);
             }
         }
     }
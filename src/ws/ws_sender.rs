@@ -3,33 +3,44 @@ use futures_util::lock::Mutex;
 use jiff::civil::Time;
 use jiff::tz::TimeZone;
 use sqlx::SqlitePool;
-use std::{process, sync::Arc, time::Instant};
-use tokio::sync::mpsc::Sender;
-use tracing::{error, trace};
+use std::{
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Notify, mpsc::Sender};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, trace, warn};
 
 use crate::C;
 use crate::alarm_schedule::CronMessage;
+use crate::app_error::AppError;
+use crate::credentials::{CredentialSender, CredentialStatus};
 use crate::request::PushRequest;
 use crate::sysinfo::SysInfo;
 use crate::ws_messages::{
-    HourMinuteMsg, MessageValues, ParsedMessage, PiStatus, Response, StructuredResponse,
-    TestRequest,
+    Budget, ClientHello, CommandResult, CredentialsUpdate, ErrorCode, ErrorResponse, Hello,
+    HourMinuteMsg, MessageValues, PROTOCOL_VERSION, PROTOCOL_VERSION_MIN, ParsedMessage, PiStatus,
+    RequestLogFilter, Response, StructuredResponse, TestRequest,
 };
 use crate::{
     app_env::AppEnv,
-    db::{ModelAlarm, ModelTimezone},
+    db::{ModelAlarm, ModelRequest, ModelSetting, ModelTimezone},
     ws_messages::to_struct,
 };
 
 const ONE_HOUR_AS_SEC: i64 = 60 * 60;
 
-use super::WSWriter;
+use super::{StatsSender, WSWriter};
 
 #[derive(Debug, Clone)]
 pub struct WSSender {
     app_envs: AppEnv,
     connected_instant: Instant,
+    credentials: CredentialSender,
+    disconnect: Arc<Notify>,
     sqlite: SqlitePool,
+    stats: StatsSender,
     sx: Sender<CronMessage>,
     writer: Arc<Mutex<WSWriter>>,
     unique: Option<String>,
@@ -42,11 +53,16 @@ impl WSSender {
         sqlite: &SqlitePool,
         sx: Sender<CronMessage>,
         writer: Arc<Mutex<WSWriter>>,
+        credentials: &CredentialSender,
+        stats: &StatsSender,
     ) -> Self {
         Self {
             app_envs: C!(app_envs),
             connected_instant,
+            credentials: C!(credentials),
+            disconnect: Arc::new(Notify::new()),
             sqlite: C!(sqlite),
+            stats: C!(stats),
             sx,
             writer,
             unique: None,
@@ -58,33 +74,99 @@ impl WSSender {
         if let Some(data) = to_struct(&message) {
             match data {
                 MessageValues::Invalid(error) => error!("invalid::{error:?}"),
+                MessageValues::Rejected(error, unique) => {
+                    // The message can contain client input, so only the code is logged
+                    warn!("rejected::{:?}", error.code);
+                    self.unique = Some(unique);
+                    self.send_ack(Err(error)).await;
+                }
                 MessageValues::Valid(msg, unique) => {
                     self.unique = Some(unique);
-                    match msg {
-                        ParsedMessage::AlarmAdd(hm) => self.alarm_add(hm).await,
-                        ParsedMessage::AlarmDelete => self.alarm_delete().await,
-                        ParsedMessage::AlarmDismiss => self.alarm_dismiss().await,
-                        ParsedMessage::AlarmUpdate(hm) => self.alarm_update(hm).await,
-                        ParsedMessage::Restart => self.restart().await,
-                        ParsedMessage::Status => self.send_status().await,
-                        ParsedMessage::TestRequest(msg) => self.test_request(msg).await,
-                        ParsedMessage::TimeZone(timezone) => self.time_zone(timezone.zone).await,
+                    let restart = matches!(msg, ParsedMessage::Restart);
+                    let result = tokio::time::timeout(
+                        Duration::from_secs(self.app_envs.ws_command_timeout),
+                        self.command(msg),
+                    )
+                    .await
+                    .unwrap_or_else(|_| {
+                        error!("command timed out");
+                        Err(ErrorResponse::new(ErrorCode::Timeout, "Command timed out"))
+                    });
+                    self.send_ack(result).await;
+                    if restart {
+                        self.restart().await;
                     }
                 }
             }
+        } else {
+            // The frame itself could contain credentials, so only log its length
+            self.stats.send_modify(|stats| stats.unparseable += 1);
+            warn!(
+                "unparseable::{} bytes, total {}",
+                message.len(),
+                self.stats.borrow().unparseable
+            );
         }
     }
 
-    /// Send a test request of a given message
-    async fn test_request(&self, msg: TestRequest) {
-        if let Err(e) = PushRequest::TestRequest
-            .make_request(&self.app_envs, &self.sqlite, &msg.message)
-            .await
+    /// The command queue is full, so reject the message, if it has a unique to respond to
+    pub async fn on_overflow(&self, message: &str) {
+        warn!("command queue full");
+        if let Some(MessageValues::Valid(_, unique) | MessageValues::Rejected(_, unique)) =
+            to_struct(message)
         {
-            tracing::error!("{e}");
+            self.send_message(StructuredResponse::ack(
+                Err(ErrorResponse::new(
+                    ErrorCode::QueueFull,
+                    "Too many commands waiting to be processed",
+                )),
+                unique,
+            ))
+            .await;
+        }
+    }
+
+    /// Run a single command, with its result sent as the ack
+    async fn command(&self, msg: ParsedMessage) -> CommandResult {
+        match msg {
+            ParsedMessage::AlarmAdd(hm) => self.alarm_add(hm).await,
+            ParsedMessage::AlarmDelete => self.alarm_delete().await,
+            ParsedMessage::AlarmDismiss => self.alarm_dismiss().await,
+            ParsedMessage::AlarmUpdate(hm) => self.alarm_update(hm).await,
+            ParsedMessage::Budget => self.budget().await,
+            ParsedMessage::CredentialsReset => self.credentials_reset().await,
+            ParsedMessage::CredentialsSet(update) => self.credentials_set(update).await,
+            ParsedMessage::Hello(hello) => Self::hello(hello),
+            ParsedMessage::RequestLog(filter) => self.request_log(filter).await,
+            // Restarting happens after the ack has been sent
+            ParsedMessage::Restart => Ok(None),
+            ParsedMessage::Status => Ok(Some(self.status().await)),
+            ParsedMessage::TestRequest(msg) => self.test_request(msg).await,
+            ParsedMessage::TimeZone(timezone) => self.time_zone(timezone.zone).await,
         }
     }
 
+    /// Log an AppError, and convert it into an ErrorResponse for the client
+    fn log_error(e: &AppError) -> ErrorResponse {
+        tracing::error!("{e}");
+        ErrorResponse::from(e)
+    }
+
+    /// Send a test request of a given message
+    async fn test_request(&self, msg: TestRequest) -> CommandResult {
+        PushRequest::TestRequest
+            .make_request(
+                &self.app_envs,
+                &self.sqlite,
+                &msg.message,
+                &msg.options,
+                &[],
+            )
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        Ok(None)
+    }
+
     /// Validate that an alarm can be edited, need to be more than six hour difference
     fn valid_change(current_time: Time, alarm_hour: i8, alarm_minute: i8) -> Result<(), ()> {
         let current_as_sec =
@@ -117,61 +199,117 @@ impl WSSender {
         Ok(())
     }
 
-    /// Add a new alarm to database, and update alarm_schedule
-    async fn alarm_add(&self, hm: HourMinuteMsg) {
-        if let Err(e) = ModelAlarm::add(&self.sqlite, hm).await {
-            tracing::error!("{e}");
-            self.send_error(&format!("{e}")).await;
-        } else {
-            self.sx.send(CronMessage::Reset).await.ok();
-            self.send_status().await;
+    /// Make sure every recipient an alarm targets is configured
+    fn valid_recipients(&self, hm: &HourMinuteMsg) -> Result<(), ErrorResponse> {
+        if let Some(name) = hm.recipients.as_ref().and_then(|names| {
+            names
+                .iter()
+                .find(|name| !self.app_envs.recipients.iter().any(|i| &&i.name == name))
+        }) {
+            return Err(ErrorResponse::new(
+                ErrorCode::RecipientUnknown,
+                format!("Unknown recipient: {name}"),
+            ));
         }
+        Ok(())
+    }
+
+    /// Add a new alarm to database, and update alarm_schedule
+    async fn alarm_add(&self, hm: HourMinuteMsg) -> CommandResult {
+        self.valid_recipients(&hm)?;
+        ModelAlarm::add(&self.sqlite, hm)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        self.sx.send(CronMessage::Reset).await.ok();
+        Ok(Some(self.status().await))
     }
 
     /// Add a new alarm to database, and update alarm_schedule
-    async fn alarm_dismiss(&self) {
+    async fn alarm_dismiss(&self) -> CommandResult {
         self.sx.send(CronMessage::AlarmDismiss).await.ok();
+        Ok(None)
     }
 
-    /// Delete all alarm in database, and update alarm_schedule
-    async fn alarm_delete(&self) {
+    /// Make sure the current alarm, if there is one, is far enough away to be edited
+    async fn unlocked(&self) -> Result<(), ErrorResponse> {
         if let Ok(Some(alarm)) = ModelAlarm::get(&self.sqlite).await
             && let Some(current_time) = ModelTimezone::get(&self.sqlite).await
+            && Self::valid_change(current_time.to_time(), alarm.hour, alarm.minute).is_err()
         {
-            let current_time = current_time.to_time();
-            if Self::valid_change(current_time, alarm.hour, alarm.minute).is_ok() {
-                if let Err(e) = ModelAlarm::delete(&self.sqlite).await {
-                    tracing::error!("{e}");
-                }
-                self.sx.send(CronMessage::Reset).await.ok();
-                self.send_status().await;
-            } else {
-                self.too_close().await;
-            }
+            return Err(ErrorResponse::alarm_locked(alarm.hour, alarm.minute));
         }
+        Ok(())
+    }
+
+    /// Delete all alarm in database, and update alarm_schedule
+    async fn alarm_delete(&self) -> CommandResult {
+        self.unlocked().await?;
+        ModelAlarm::delete(&self.sqlite)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        self.sx.send(CronMessage::Reset).await.ok();
+        Ok(Some(self.status().await))
     }
 
     /// Update the alarm in the database, and update alarm_schedule
-    async fn alarm_update(&self, hm: HourMinuteMsg) {
-        if let Ok(Some(alarm)) = ModelAlarm::get(&self.sqlite).await
-            && let Some(current_time) = ModelTimezone::get(&self.sqlite).await
-        {
-            let current_time = current_time.to_time();
-            if Self::valid_change(current_time, alarm.hour, alarm.minute).is_ok() {
-                if let Err(e) = ModelAlarm::update(&self.sqlite, hm).await {
-                    tracing::error!("{e}");
-                }
-                self.sx.send(CronMessage::Reset).await.ok();
-                self.send_status().await;
-            } else {
-                self.too_close().await;
-            }
+    async fn alarm_update(&self, hm: HourMinuteMsg) -> CommandResult {
+        self.valid_recipients(&hm)?;
+        ModelAlarm::get(&self.sqlite)
+            .await
+            .map_err(|e| Self::log_error(&e))?
+            .ok_or_else(|| ErrorResponse::new(ErrorCode::AlarmMissing, "No alarm to update"))?;
+        self.unlocked().await?;
+        ModelAlarm::update(&self.sqlite, hm)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        self.sx.send(CronMessage::Reset).await.ok();
+        Ok(Some(self.status().await))
+    }
+
+    /// The current credentials, with the update applied, every recipient must be valid before the update is stored
+    async fn validate_update(
+        &self,
+        update: &CredentialsUpdate,
+    ) -> Result<CredentialStatus, AppError> {
+        let mut candidate = ModelSetting::apply(&self.sqlite, &self.app_envs).await?;
+        if let Some(token) = update.token_app.as_ref() {
+            candidate.token_app = C!(token);
+        }
+        for (name, user) in &update.users {
+            let recipient = candidate
+                .recipients
+                .iter_mut()
+                .find(|i| &i.name == name)
+                .ok_or_else(|| AppError::RecipientInvalid(C!(name)))?;
+            recipient.user.clone_from(user);
         }
+        let status = CredentialStatus::validate_all(&candidate).await;
+        status.check_valid()?;
+        Ok(status)
     }
 
-    async fn too_close(&self) {
-        self.send_error("Current time too close to alarm to edit")
-            .await;
+    /// Validate, and then store, new Pushover credentials, which are used for every push from then on
+    async fn credentials_set(&self, update: CredentialsUpdate) -> CommandResult {
+        let status = self
+            .validate_update(&update)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        ModelSetting::set_credentials(&self.sqlite, &update)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        tracing::info!("credentials updated: {update:?}");
+        self.credentials.send_replace(Some(status));
+        Ok(Some(self.status().await))
+    }
+
+    /// Remove any stored credentials, so that the env values are used again
+    async fn credentials_reset(&self) -> CommandResult {
+        ModelSetting::delete_credentials(&self.sqlite)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        self.credentials
+            .send_replace(CredentialStatus::validate(&self.app_envs).await);
+        Ok(Some(self.status().await))
     }
 
     /// Force quite program, assumes running in an auto-restart container, or systemd, in order to start again immediately
@@ -182,22 +320,17 @@ impl WSSender {
 
     /// Change the timezone in database to new given database,
     /// also update timezone in alarm scheduler
-    async fn time_zone(&self, zone: String) {
-        if let Some(alarm) = ModelAlarm::get(&self.sqlite).await.unwrap_or_default()
-            && let Some(current_time) = ModelTimezone::get(&self.sqlite).await
-            && Self::valid_change(current_time.to_time(), alarm.hour, alarm.minute).is_err()
-        {
-            self.too_close().await;
-            return;
-        }
-
-        if TimeZone::get(&zone).is_ok() {
-            ModelTimezone::update(&self.sqlite, &zone).await.ok();
-            self.sx.send(CronMessage::Reset).await.ok();
-            self.send_status().await;
-        } else {
-            self.send_error("Invalid timezone").await;
+    async fn time_zone(&self, zone: String) -> CommandResult {
+        self.unlocked().await?;
+        if TimeZone::get(&zone).is_err() {
+            return Err(ErrorResponse::new(
+                ErrorCode::TimezoneInvalid,
+                "Invalid timezone",
+            ));
         }
+        ModelTimezone::update(&self.sqlite, &zone).await.ok();
+        self.sx.send(CronMessage::Reset).await.ok();
+        Ok(Some(self.status().await))
     }
 
     /// Send a message to the socket
@@ -207,34 +340,95 @@ impl WSSender {
         response: Response,
         cache: Option<bool>,
         unique: Option<String>,
-    ) {
-        match self
-            .writer
-            .lock()
+    ) -> bool {
+        self.send_message(StructuredResponse::data(response, cache, unique))
             .await
-            .send(StructuredResponse::data(response, cache, unique))
-            .await
-        {
-            Ok(()) => trace!("Message sent"),
+    }
+
+    /// Write a message to the ws connection, on failure the connection is torn down, so that a new one is opened
+    async fn send_message(&self, message: Message) -> bool {
+        match self.writer.lock().await.send(message).await {
+            Ok(()) => {
+                trace!("Message sent");
+                true
+            }
             Err(e) => {
-                error!("send_ws_response::SEND-ERROR::{e:?}");
-                process::exit(1);
+                self.stats.send_modify(|stats| stats.send_failures += 1);
+                error!(
+                    "send_message::SEND-ERROR::{e:?}, total {}",
+                    self.stats.borrow().send_failures
+                );
+                self.disconnect.notify_one();
+                false
             }
         }
     }
 
-    /// Send a unique error message
-    pub async fn send_error(&self, message: &str) {
-        self.send_ws_response(Response::Error(message.to_owned()), None, C!(self.unique))
+    /// Resolves once a message has failed to send, and the connection should be closed
+    pub async fn disconnected(&self) {
+        self.disconnect.notified().await;
+    }
+
+    /// Send the result of the current request, always with its unique
+    async fn send_ack(&self, result: CommandResult) {
+        self.send_message(StructuredResponse::ack(
+            result,
+            C!(self.unique).unwrap_or_default(),
+        ))
+        .await;
+    }
+
+    /// Send an event generated outside of a client request, false if it couldn't be sent
+    pub async fn send_event(&self, response: Response) -> bool {
+        self.send_ws_response(response, None, None).await
+    }
+
+    /// The requests remaining before each rate limit is reached
+    async fn budget(&self) -> CommandResult {
+        let budget = Budget::get(&self.app_envs, &self.sqlite)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        Ok(Some(Response::Budget(budget)))
+    }
+
+    /// The most recent requests that match the filter
+    async fn request_log(&self, filter: RequestLogFilter) -> CommandResult {
+        let requests = ModelRequest::get_log(&self.sqlite, &filter)
+            .await
+            .map_err(|e| Self::log_error(&e))?;
+        Ok(Some(Response::RequestLog(requests)))
+    }
+
+    /// Agree on a protocol version with the client, the highest that both support
+    fn hello(hello: ClientHello) -> CommandResult {
+        if hello.protocol < PROTOCOL_VERSION_MIN {
+            return Err(ErrorResponse::protocol_unsupported(hello.protocol));
+        }
+        Ok(Some(Response::Hello(Hello::new(hello.protocol))))
+    }
+
+    /// Send the protocol version, and supported commands, on connect
+    pub async fn send_hello(&self) {
+        self.send_ws_response(Response::Hello(Hello::new(PROTOCOL_VERSION)), None, None)
             .await;
     }
 
-    /// Generate, and send, pi information
-    pub async fn send_status(&self) {
+    /// Generate pi information
+    async fn status(&self) -> Response {
         let info = SysInfo::new(&self.sqlite, &self.app_envs).await;
         let alarms = ModelAlarm::get(&self.sqlite).await.unwrap_or_default();
-        let info = PiStatus::new(info, alarms, self.connected_instant.elapsed().as_secs());
-        self.send_ws_response(Response::Status(info), Some(true), None)
+        Response::Status(Box::new(PiStatus::new(
+            info,
+            alarms,
+            self.connected_instant.elapsed().as_secs(),
+            C!(self.credentials.borrow()),
+            *self.stats.borrow(),
+        )))
+    }
+
+    /// Send pi information, not in response to a request, so without a unique
+    pub async fn send_status(&self) {
+        self.send_ws_response(self.status().await, Some(true), None)
             .await;
     }
 
@@ -324,4 +518,26 @@ mod tests {
         test(alarm, (0, 15), false);
         test(alarm, (0, 16), true);
     }
+
+    #[test]
+    fn test_ws_sender_hello() {
+        let test = |protocol: u16, expected: Option<u16>| match WSSender::hello(ClientHello {
+            protocol,
+        }) {
+            Ok(Some(Response::Hello(hello))) => {
+                assert_eq!(Some(hello.protocol), expected);
+                assert_eq!(hello.version, env!("CARGO_PKG_VERSION"));
+                assert!(hello.commands.iter().any(|i| i == "hello"));
+            }
+            Err(error) => {
+                assert!(expected.is_none());
+                assert_eq!(error.code, ErrorCode::ProtocolUnsupported);
+            }
+            Ok(_) => unreachable!("Shouldn't have matched this"),
+        };
+        test(0, None);
+        test(PROTOCOL_VERSION, Some(PROTOCOL_VERSION));
+        // A newer client is told to use the current version
+        test(PROTOCOL_VERSION + 1, Some(PROTOCOL_VERSION));
+    }
 }
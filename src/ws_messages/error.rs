@@ -0,0 +1,204 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_error::AppError,
+    ws_messages::{PROTOCOL_VERSION, PROTOCOL_VERSION_MIN},
+};
+
+/// Stable, machine readable, error codes, clients should match on these rather than the message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The alarm is too close to be edited, details contain the time it can next be edited
+    AlarmLocked,
+    /// There's no alarm to update
+    AlarmMissing,
+    /// The command isn't supported by this version, details contain the command name
+    CommandUnsupported,
+    CredentialsInvalid,
+    /// Database, or other, failure not caused by the request itself
+    Internal,
+    NotifyFailed,
+    /// The client's protocol version isn't supported, details contain the supported range
+    ProtocolUnsupported,
+    Pushover,
+    /// Too many commands are waiting to be processed, so this one was dropped
+    QueueFull,
+    RateLimited,
+    RecipientUnknown,
+    /// The command took too long, and was abandoned
+    Timeout,
+    TimezoneInvalid,
+    /// The message was valid json, but failed validation, details contain the field and reason
+    Validation,
+    /// Any code not known to this version, only ever deserialized
+    #[serde(other)]
+    Unknown,
+}
+
+/// Optional extra information about an error
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum ErrorDetails {
+    /// The alarm can next be edited at this time, in the current timezone
+    UnlockAt { hour: i8, minute: i8 },
+    /// The path to the field that failed validation, and why
+    Field { path: String, reason: String },
+    /// The name of the unsupported command
+    Command { name: String },
+    /// The range of protocol versions supported
+    Protocol { min: u16, max: u16 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<ErrorDetails>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: ErrorDetails) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// The alarm, set at the given time, can't be edited until a minute after it's gone off
+    pub fn alarm_locked(alarm_hour: i8, alarm_minute: i8) -> Self {
+        let unlock = jiff::civil::time(alarm_hour, alarm_minute, 0, 0)
+            .wrapping_add(jiff::SignedDuration::from_mins(1));
+        Self::new(
+            ErrorCode::AlarmLocked,
+            "Current time too close to alarm to edit",
+        )
+        .with_details(ErrorDetails::UnlockAt {
+            hour: unlock.hour(),
+            minute: unlock.minute(),
+        })
+    }
+
+    /// An incoming message failed validation at the given field path
+    pub fn validation(path: impl Into<String>, reason: impl Into<String>) -> Self {
+        let (path, reason) = (path.into(), reason.into());
+        Self::new(ErrorCode::Validation, format!("{path}: {reason}"))
+            .with_details(ErrorDetails::Field { path, reason })
+    }
+
+    /// A command that this version doesn't know about
+    pub fn command_unsupported(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self::new(
+            ErrorCode::CommandUnsupported,
+            format!("Unsupported command: {name}"),
+        )
+        .with_details(ErrorDetails::Command { name })
+    }
+
+    /// A protocol version outside of the supported range
+    pub fn protocol_unsupported(protocol: u16) -> Self {
+        Self::new(
+            ErrorCode::ProtocolUnsupported,
+            format!("Unsupported protocol version: {protocol}"),
+        )
+        .with_details(ErrorDetails::Protocol {
+            min: PROTOCOL_VERSION_MIN,
+            max: PROTOCOL_VERSION,
+        })
+    }
+}
+
+impl From<&AppError> for ErrorResponse {
+    fn from(value: &AppError) -> Self {
+        let code = match value {
+            AppError::CredentialsInvalid(_) => ErrorCode::CredentialsInvalid,
+            AppError::NotifyFailed(_) => ErrorCode::NotifyFailed,
+            AppError::Pushover(_) => ErrorCode::Pushover,
+            AppError::RecipientInvalid(_) => ErrorCode::RecipientUnknown,
+            AppError::TooManyRequests(_) | AppError::TooManyRequestsDay(_) => {
+                ErrorCode::RateLimited
+            }
+            _ => ErrorCode::Internal,
+        };
+        Self::new(code, value.detail())
+    }
+}
+
+/// message_error
+///
+/// cargo watch -q -c -w src/ -x 'test message_error -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::S;
+
+    #[test]
+    fn message_error_serialize() {
+        let result = serde_json::to_string(&ErrorResponse::new(
+            ErrorCode::TimezoneInvalid,
+            "Invalid timezone",
+        ))
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"{"code":"timezone_invalid","message":"Invalid timezone"}"#
+        );
+
+        let result = serde_json::to_string(&ErrorResponse::alarm_locked(6, 15)).unwrap();
+        assert_eq!(
+            result,
+            r#"{"code":"alarm_locked","message":"Current time too close to alarm to edit","details":{"kind":"unlock_at","hour":6,"minute":16}}"#
+        );
+    }
+
+    #[test]
+    fn message_error_alarm_locked_wraps() {
+        let result = ErrorResponse::alarm_locked(23, 59);
+        assert_eq!(
+            result.details,
+            Some(ErrorDetails::UnlockAt { hour: 0, minute: 0 })
+        );
+    }
+
+    #[test]
+    // Codes from a newer version are still deserialized
+    fn message_error_deserialize_unknown() {
+        let result = serde_json::from_str::<ErrorResponse>(
+            r#"{"code":"something_new","message":"message"}"#,
+        )
+        .unwrap();
+        assert_eq!(result.code, ErrorCode::Unknown);
+        assert_eq!(result.details, None);
+    }
+
+    #[test]
+    fn message_error_from_app_error() {
+        let test = |error: AppError, code: ErrorCode| {
+            let result = ErrorResponse::from(&error);
+            assert_eq!(result.code, code);
+            assert_eq!(result.message, error.to_string());
+        };
+        test(
+            AppError::RecipientInvalid(S!("sam")),
+            ErrorCode::RecipientUnknown,
+        );
+        test(AppError::TooManyRequests(10), ErrorCode::RateLimited);
+        test(AppError::TooManyRequestsDay(10), ErrorCode::RateLimited);
+        test(
+            AppError::CredentialsInvalid(S!("default")),
+            ErrorCode::CredentialsInvalid,
+        );
+        test(AppError::NotifyFailed(S!("all")), ErrorCode::NotifyFailed);
+        test(AppError::WsStatus, ErrorCode::Internal);
+    }
+}
@@ -1,39 +1,136 @@
 use super::serializer::IncomingSerializer as is;
 
+use schemars::{JsonSchema, generate::SchemaSettings};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, sync::LazyLock};
+
+use crate::{C, S, notify::Channel, request::RequestKind, ws_messages::ErrorResponse};
 
 #[derive(Debug)]
 pub enum MessageValues {
     Valid(ParsedMessage, String),
-    Invalid(ErrorData),
+    Invalid(ErrorResponse),
+    /// Json with a unique, but the data failed validation, so respond with the reason
+    Rejected(ErrorResponse, String),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case", tag = "name", content = "body")]
 pub enum ParsedMessage {
     AlarmAdd(HourMinuteMsg),
     AlarmDelete,
     AlarmUpdate(HourMinuteMsg),
     AlarmDismiss,
+    Budget,
+    CredentialsReset,
+    CredentialsSet(CredentialsUpdate),
+    Hello(ClientHello),
+    RequestLog(RequestLogFilter),
     Restart,
     Status,
     TestRequest(TestRequest),
     TimeZone(TimeZone),
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+impl ParsedMessage {
+    /// The name of every command, as sent by a client, included in the hello message.
+    /// Taken from the schema of the enum, so that every variant is always included
+    pub fn commands() -> &'static [String] {
+        static COMMANDS: LazyLock<Vec<String>> = LazyLock::new(|| {
+            let schema = SchemaSettings::draft2020_12()
+                .for_deserialize()
+                .into_generator()
+                .into_root_schema_for::<ParsedMessage>();
+            let mut output = schema
+                .get("oneOf")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|i| i.pointer("/properties/name/const")?.as_str())
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>();
+            output.sort();
+            output
+        });
+        &COMMANDS
+    }
+}
+
+/// The protocol version the client was built against
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub struct ClientHello {
+    pub protocol: u16,
+}
+
+#[derive(Deserialize, Debug, Serialize, JsonSchema)]
 pub struct TestRequest {
     #[serde(deserialize_with = "is::message")]
+    #[schemars(length(max = 100))]
     pub message: String,
+    #[serde(default, deserialize_with = "is::push_options")]
+    pub options: PushOptions,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+/// Optional Pushover message parameters, sent with every push of an alarm or test request
+#[derive(
+    Deserialize,
+    Debug,
+    Serialize,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    sqlx::FromRow,
+    JsonSchema,
+)]
+#[schemars(extend(
+    "dependentRequired" = { "url_title": ["url"] },
+    "not" = {
+        "properties": { "html": { "const": true }, "monospace": { "const": true } },
+        "required": ["html", "monospace"]
+    }
+))]
+pub struct PushOptions {
+    #[serde(default, deserialize_with = "is::title")]
+    #[schemars(length(max = 250))]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "is::sound")]
+    #[schemars(regex(pattern = "^[A-Za-z0-9_-]{1,20}$"))]
+    pub sound: Option<String>,
+    #[serde(default, deserialize_with = "is::url")]
+    #[schemars(url, length(max = 512))]
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "is::url_title")]
+    #[schemars(length(max = 100))]
+    pub url_title: Option<String>,
+    #[serde(default)]
+    pub html: bool,
+    #[serde(default)]
+    pub monospace: bool,
+    #[serde(default, deserialize_with = "is::ttl")]
+    #[schemars(range(min = 1))]
+    pub ttl: Option<u32>,
+    #[serde(default, deserialize_with = "is::timestamp")]
+    #[schemars(range(min = 1))]
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Serialize, JsonSchema)]
 pub struct HourMinuteMsg {
     #[serde(deserialize_with = "is::hour")]
+    #[schemars(range(max = 23))]
     pub hour: u8,
     #[serde(deserialize_with = "is::minute")]
+    #[schemars(range(max = 59))]
     pub minute: u8,
     pub message: Option<String>,
+    #[serde(default, deserialize_with = "is::recipients")]
+    #[schemars(length(max = 10), inner(regex(pattern = "^[A-Za-z0-9_-]{1,32}$")))]
+    pub recipients: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "is::push_options")]
+    pub options: PushOptions,
 }
 
 #[cfg(debug_assertions)]
@@ -43,45 +140,143 @@ impl From<(u8, u8, Option<String>)> for HourMinuteMsg {
             hour: data.0,
             minute: data.1,
             message: data.2,
+            recipients: None,
+            options: PushOptions::default(),
+        }
+    }
+}
+
+const fn default_log_limit() -> u16 {
+    50
+}
+
+/// Filters for the request log, every filter is optional
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct RequestLogFilter {
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    #[serde(default)]
+    pub kind: Option<RequestKind>,
+    #[serde(default)]
+    pub alarm_id: Option<i64>,
+    #[serde(default, deserialize_with = "is::recipient")]
+    #[schemars(regex(pattern = "^[A-Za-z0-9_-]{1,32}$"))]
+    pub recipient: Option<String>,
+    #[serde(default)]
+    pub delivered: Option<bool>,
+    #[serde(default, deserialize_with = "is::timestamp")]
+    #[schemars(range(min = 1))]
+    pub from: Option<i64>,
+    #[serde(default, deserialize_with = "is::timestamp")]
+    #[schemars(range(min = 1))]
+    pub to: Option<i64>,
+    #[serde(default = "default_log_limit", deserialize_with = "is::log_limit")]
+    #[schemars(range(min = 1, max = 500))]
+    pub limit: u16,
+}
+
+impl Default for RequestLogFilter {
+    fn default() -> Self {
+        Self {
+            channel: None,
+            kind: None,
+            alarm_id: None,
+            recipient: None,
+            delivered: None,
+            from: None,
+            to: None,
+            limit: default_log_limit(),
         }
     }
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+/// New Pushover credentials, anything not included is left unchanged.
+/// `users` maps recipient names to their new user key
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct CredentialsUpdate {
+    #[serde(default, deserialize_with = "is::pushover_key")]
+    #[schemars(regex(pattern = "^[A-Za-z0-9]{30}$"))]
+    pub token_app: Option<String>,
+    #[serde(default, deserialize_with = "is::pushover_users")]
+    #[schemars(extend(
+        "maxProperties" = 10,
+        "propertyNames" = { "pattern": "^[A-Za-z0-9_-]{1,32}$" },
+        "additionalProperties" = { "type": "string", "pattern": "^[A-Za-z0-9]{30}$" }
+    ))]
+    pub users: BTreeMap<String, String>,
+}
+
+/// Keys are secrets, so only the recipient names are ever shown
+impl fmt::Debug for CredentialsUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CredentialsUpdate")
+            .field("token_app", &self.token_app.as_ref().map(|_| "***"))
+            .field("users", &self.users.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, JsonSchema)]
 pub struct TimeZone {
+    /// An IANA timezone name, such as "Europe/London"
     #[serde(deserialize_with = "is::timezone")]
     pub zone: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-struct StructuredMessage {
+pub(super) struct StructuredMessage {
     data: Option<ParsedMessage>,
-    error: Option<ErrorData>,
+    error: Option<ErrorResponse>,
     unique: String,
 }
 
-// TODO - this is, at the moment, pointless
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "snake_case", tag = "error", content = "message")]
-pub enum ErrorData {
-    Something(String),
+/// The serde_json error, without the line and column position
+fn error_reason(error: &serde_json::Error) -> String {
+    let reason = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+    reason
+        .strip_suffix(&position)
+        .map_or_else(|| C!(reason), ToOwned::to_owned)
 }
 
-// Change this to a Result<MessageValues, AppError>?
+/// Parse an incoming message, `None` if it isn't json with a unique, and so can't be responded to
 pub fn to_struct(input: &str) -> Option<MessageValues> {
-    let user_serialized = serde_json::from_str::<StructuredMessage>(input);
-    if let Ok(data) = user_serialized {
-        if let Some(message) = data.error {
-            return Some(MessageValues::Invalid(message));
-        }
-        if let Some(message) = data.data {
-            return Some(MessageValues::Valid(message, data.unique));
-        }
-        None
-    } else {
-        let error_serialized = serde_json::from_str::<ErrorData>(input);
-        error_serialized.map_or(None, |data| Some(MessageValues::Invalid(data)))
+    let value = serde_json::from_str::<serde_json::Value>(input).ok()?;
+    let deserializer = &mut serde_json::Deserializer::from_str(input);
+    match serde_path_to_error::deserialize::<_, StructuredMessage>(deserializer) {
+        Ok(data) => Some(match (data.error, data.data) {
+            (Some(error), _) => MessageValues::Invalid(error),
+            (None, Some(message)) => MessageValues::Valid(message, data.unique),
+            (None, None) => MessageValues::Rejected(
+                ErrorResponse::validation("data", "missing data"),
+                data.unique,
+            ),
+        }),
+        Err(e) => value
+            .get("unique")
+            .and_then(serde_json::Value::as_str)
+            .map_or_else(
+                || {
+                    serde_json::from_value::<ErrorResponse>(C!(value))
+                        .ok()
+                        .map(MessageValues::Invalid)
+                },
+                |unique| {
+                    let error = match value
+                        .pointer("/data/name")
+                        .and_then(serde_json::Value::as_str)
+                    {
+                        Some(name) if !ParsedMessage::commands().iter().any(|i| i == name) => {
+                            ErrorResponse::command_unsupported(name)
+                        }
+                        _ => {
+                            ErrorResponse::validation(e.path().to_string(), error_reason(e.inner()))
+                        }
+                    };
+                    Some(MessageValues::Rejected(error, S!(unique)))
+                },
+            ),
     }
 }
 
@@ -92,6 +287,7 @@ pub fn to_struct(input: &str) -> Option<MessageValues> {
 #[expect(clippy::unwrap_used)]
 mod tests {
     use super::*;
+    use crate::ws_messages::{ErrorCode, ErrorDetails};
 
     #[test]
     fn message_incoming_parse_invalid() {
@@ -104,6 +300,119 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    // Validation errors contain the path to the field, and the reason, and are tied to the unique
+    fn message_incoming_parse_rejected() {
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 24, "minute": 15 } }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Rejected(error, unique)) => {
+                assert_eq!(unique, "random_string");
+                assert_eq!(error.code, ErrorCode::Validation);
+                assert_eq!(error.message, "data.body.hour: 24, not in range 0..=23");
+                assert_eq!(
+                    error.details,
+                    Some(ErrorDetails::Field {
+                        path: S!("data.body.hour"),
+                        reason: S!("24, not in range 0..=23"),
+                    })
+                );
+            }
+            result => unreachable!("Shouldn't have matched {result:?}"),
+        }
+
+        let message = "a".repeat(101);
+        let data = format!(
+            r#" {{ "data": {{ "name": "test_request", "body": {{ "message": "{message}" }} }}, "unique": "random_string" }}"#
+        );
+        match to_struct(&data) {
+            Some(MessageValues::Rejected(error, _)) => {
+                assert_eq!(error.message, "data.body.message: message too long");
+            }
+            result => unreachable!("Shouldn't have matched {result:?}"),
+        }
+
+        // No data
+        let data = r#" { "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Rejected(error, _)) => {
+                assert_eq!(error.message, "data: missing data");
+            }
+            result => unreachable!("Shouldn't have matched {result:?}"),
+        }
+
+        // Without a unique there's nothing to respond to
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 24, "minute": 15 } } }"#;
+        assert!(to_struct(data).is_none());
+        assert!(to_struct("not json").is_none());
+    }
+
+    #[test]
+    fn message_incoming_parse_hello() {
+        let data = r#" { "data": { "name": "hello", "body": { "protocol": 1 } }, "unique": "random_string" }"#;
+        assert!(matches!(
+            to_struct(data),
+            Some(MessageValues::Valid(
+                ParsedMessage::Hello(ClientHello { protocol: 1 }),
+                _
+            ))
+        ));
+        test_is_rejected(
+            r#" { "data": { "name": "hello", "body": { "protocol": -1 } }, "unique": "random_string" }"#,
+        );
+    }
+
+    #[test]
+    // Unknown commands are rejected with their own error code, every listed command is known
+    fn message_incoming_parse_unsupported() {
+        let data = r#" { "data": { "name": "alarm_snooze" }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Rejected(error, unique)) => {
+                assert_eq!(unique, "random_string");
+                assert_eq!(error.code, ErrorCode::CommandUnsupported);
+                assert_eq!(
+                    error.details,
+                    Some(ErrorDetails::Command {
+                        name: S!("alarm_snooze")
+                    })
+                );
+            }
+            result => unreachable!("Shouldn't have matched {result:?}"),
+        }
+
+        for name in ParsedMessage::commands() {
+            let data = format!(
+                r#" {{ "data": {{ "name": "{name}", "body": {{ "invalid": true }} }}, "unique": "random_string" }}"#
+            );
+            if let Some(MessageValues::Rejected(error, _)) = to_struct(&data) {
+                assert_ne!(error.code, ErrorCode::CommandUnsupported);
+                assert!(!error.message.contains("unknown variant"));
+            }
+        }
+    }
+
+    #[test]
+    // Every variant, including those without a body, is a listed command
+    fn message_incoming_commands() {
+        assert_eq!(
+            ParsedMessage::commands(),
+            [
+                "alarm_add",
+                "alarm_delete",
+                "alarm_dismiss",
+                "alarm_update",
+                "budget",
+                "credentials_reset",
+                "credentials_set",
+                "hello",
+                "request_log",
+                "restart",
+                "status",
+                "test_request",
+                "time_zone",
+            ]
+        );
+    }
+
     #[test]
     fn message_incoming_parse_alarm_add_valid() {
         let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 15 } }, "unique": "random_string" }"#;
@@ -122,6 +431,195 @@ mod tests {
         }
     }
 
+    #[test]
+    fn message_incoming_parse_alarm_add_recipients_valid() {
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 15, "recipients": ["jack", "sam"] } }, "unique": "random_string" }"#;
+
+        let result = to_struct(data);
+
+        match result {
+            Some(MessageValues::Valid(ParsedMessage::AlarmAdd(data), _)) => {
+                assert_eq!(
+                    data.recipients,
+                    Some(vec!["jack".to_owned(), "sam".to_owned()])
+                );
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 15, "recipients": null } }, "unique": "random_string" }"#;
+
+        let result = to_struct(data);
+
+        match result {
+            Some(MessageValues::Valid(ParsedMessage::AlarmAdd(data), _)) => {
+                assert!(data.recipients.is_none());
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+    }
+
+    #[test]
+    fn message_incoming_parse_alarm_add_options_valid() {
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 15, "options": { "title": "wake up", "sound": "siren", "url": "https://www.example.com", "url_title": "example", "html": true, "ttl": 60 } } }, "unique": "random_string" }"#;
+
+        let result = to_struct(data);
+
+        match result {
+            Some(MessageValues::Valid(ParsedMessage::AlarmAdd(data), _)) => {
+                assert_eq!(
+                    data.options,
+                    PushOptions {
+                        title: Some("wake up".to_owned()),
+                        sound: Some("siren".to_owned()),
+                        url: Some("https://www.example.com".to_owned()),
+                        url_title: Some("example".to_owned()),
+                        html: true,
+                        monospace: false,
+                        ttl: Some(60),
+                        timestamp: None,
+                    }
+                );
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        // No options
+        let data = r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 15 } }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Valid(ParsedMessage::AlarmAdd(data), _)) => {
+                assert_eq!(data.options, PushOptions::default());
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+    }
+
+    #[test]
+    fn message_incoming_parse_test_request_options() {
+        let data = r#" { "data": { "name": "test_request", "body": { "message": "test", "options": { "monospace": true, "timestamp": 1700000000 } } }, "unique": "random_string" }"#;
+
+        match to_struct(data) {
+            Some(MessageValues::Valid(ParsedMessage::TestRequest(data), _)) => {
+                assert_eq!(data.message, "test");
+                assert!(data.options.monospace);
+                assert_eq!(data.options.timestamp, Some(1_700_000_000));
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        // html and monospace
+        test_is_rejected(
+            r#" { "data": { "name": "test_request", "body": { "message": "test", "options": { "html": true, "monospace": true } } }, "unique": "random_string" }"#,
+        );
+        // invalid ttl
+        test_is_rejected(
+            r#" { "data": { "name": "test_request", "body": { "message": "test", "options": { "ttl": 0 } } }, "unique": "random_string" }"#,
+        );
+    }
+
+    #[test]
+    fn message_incoming_parse_budget_valid() {
+        let data = r#" { "data": { "name": "budget" }, "unique": "random_string" }"#;
+
+        let result = to_struct(data);
+
+        assert!(matches!(
+            result,
+            Some(MessageValues::Valid(ParsedMessage::Budget, _))
+        ));
+    }
+
+    #[test]
+    fn message_incoming_parse_credentials() {
+        let token = "a".repeat(30);
+        let user = "b".repeat(30);
+        let data = format!(
+            r#" {{ "data": {{ "name": "credentials_set", "body": {{ "token_app": "{token}", "users": {{ "jack": "{user}" }} }} }}, "unique": "random_string" }}"#
+        );
+        match to_struct(&data) {
+            Some(MessageValues::Valid(ParsedMessage::CredentialsSet(update), _)) => {
+                assert_eq!(update.token_app, Some(C!(token)));
+                assert_eq!(update.users.get("jack"), Some(&user));
+                // Keys aren't shown when debug printed
+                let debug = format!("{update:?}");
+                assert!(!debug.contains(&token));
+                assert!(!debug.contains(&user));
+                assert!(debug.contains("jack"));
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        // Every field is optional
+        let data =
+            r#" { "data": { "name": "credentials_set", "body": {} }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Valid(ParsedMessage::CredentialsSet(update), _)) => {
+                assert_eq!(update, CredentialsUpdate::default());
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        test_is_rejected(
+            r#" { "data": { "name": "credentials_set", "body": { "token_app": "short" } }, "unique": "random_string" }"#,
+        );
+
+        let data = r#" { "data": { "name": "credentials_reset" }, "unique": "random_string" }"#;
+        assert!(matches!(
+            to_struct(data),
+            Some(MessageValues::Valid(ParsedMessage::CredentialsReset, _))
+        ));
+    }
+
+    #[test]
+    fn message_incoming_parse_request_log_valid() {
+        let data =
+            r#" { "data": { "name": "request_log", "body": {} }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Valid(ParsedMessage::RequestLog(filter), _)) => {
+                assert_eq!(filter, RequestLogFilter::default());
+                assert_eq!(filter.limit, 50);
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+
+        let data = r#" { "data": { "name": "request_log", "body": { "channel": "pushover", "kind": "alarm", "alarm_id": 1, "recipient": "jack", "delivered": false, "from": 1700000000, "to": 1700003600, "limit": 10 } }, "unique": "random_string" }"#;
+        match to_struct(data) {
+            Some(MessageValues::Valid(ParsedMessage::RequestLog(filter), _)) => {
+                assert_eq!(
+                    filter,
+                    RequestLogFilter {
+                        channel: Some(Channel::Pushover),
+                        kind: Some(RequestKind::Alarm),
+                        alarm_id: Some(1),
+                        recipient: Some(String::from("jack")),
+                        delivered: Some(false),
+                        from: Some(1_700_000_000),
+                        to: Some(1_700_003_600),
+                        limit: 10,
+                    }
+                );
+            }
+            _ => unreachable!("Shouldn't have matched this"),
+        }
+    }
+
+    #[test]
+    fn message_incoming_parse_request_log_invalid() {
+        for body in [
+            r#"{ "channel": "email" }"#,
+            r#"{ "kind": "snooze" }"#,
+            r#"{ "recipient": "" }"#,
+            r#"{ "from": 0 }"#,
+            r#"{ "limit": 0 }"#,
+            r#"{ "limit": 501 }"#,
+        ] {
+            let data = format!(
+                r#" {{ "data": {{ "name": "request_log", "body": {body} }}, "unique": "random_string" }}"#
+            );
+            test_is_rejected(&data);
+        }
+    }
+
     #[test]
     fn message_incoming_parse_update_alarm_valid() {
         let data = r#" { "data": { "name" :"alarm_update", "body": { "hour": 6, "minute": 15 } }, "unique": "random_string" }"#;
@@ -138,38 +636,50 @@ mod tests {
         }
     }
 
+    /// Invalid json, or no valid unique, so nothing to respond to
     fn test_is_none(json: &str) {
         let result = to_struct(json);
         assert!(result.is_none());
     }
 
+    /// Invalid data, with a unique, is rejected with a validation error
+    fn test_is_rejected(json: &str) {
+        match to_struct(json) {
+            Some(MessageValues::Rejected(error, unique)) => {
+                assert_eq!(error.code, ErrorCode::Validation);
+                assert!(!unique.is_empty());
+            }
+            result => unreachable!("Shouldn't have matched {result:?}"),
+        }
+    }
+
     #[test]
     fn message_incoming_parse_alarm_add_invalid() {
         // No body
-        test_is_none(r#"{ "data": { "name": "alarm_add" }, "unique":"true"}"#);
+        test_is_rejected(r#"{ "data": { "name": "alarm_add" }, "unique":"true"}"#);
 
         // Empty body
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_add", "body": "" }, "unique": "random_string" }"#,
         );
 
         // Empty body object
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_add", "body": { } }, "unique": "random_string" }"#,
         );
 
         // No hours
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_add", "body": { "minute": 6 } }, "unique": "random_string"}"#,
         );
 
         // invalid hours - number as string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_add", "body": { "hour": "6", "minute": 4 } }, "unique": "random_string" }"#,
         );
 
         // invalid hours - string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_add", "body": { "hour": "string", "minute": 4 } }, "unique": "random_string" }"#,
         );
 
@@ -179,20 +689,36 @@ mod tests {
         );
 
         // invalid minute - number as string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_add", "body": { "hour" :6, "minute": "4" } }, "unique": "random_string" }"#,
         );
 
         // invalid minute - string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": "string" } }, "unique": "random_string" }"#,
         );
 
         // invalid minute- > 59
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 60 } }, "unique": "random_string"}"#,
         );
 
+        // invalid recipients
+        test_is_rejected(
+            r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 9, "recipients": "jack" } }, "unique": "random_string"}"#,
+        );
+
+        // invalid options
+        test_is_rejected(
+            r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 9, "options": { "url": "not a url" } } }, "unique": "random_string"}"#,
+        );
+        test_is_rejected(
+            r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 9, "options": { "url_title": "no url" } } }, "unique": "random_string"}"#,
+        );
+        test_is_rejected(
+            r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 9, "recipients": ["ja,ck"] } }, "unique": "random_string"}"#,
+        );
+
         // invalid unique
         test_is_none(
             r#" { "data": { "name": "alarm_add", "body": { "hour": 6, "minute": 9 } }, "unique": 1 }"#,
@@ -205,30 +731,30 @@ mod tests {
     #[test]
     fn message_incoming_parse_alarm_update_invalid() {
         // No body
-        test_is_none(r#"{ "data": { "name": "alarm_update" }, "unique":"true"}"#);
+        test_is_rejected(r#"{ "data": { "name": "alarm_update" }, "unique":"true"}"#);
 
         // Empty body
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_update", "body": "" }, "unique": "random_string" }"#,
         );
 
         // Empty body object
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_update", "body": { } }, "unique": "random_string" }"#,
         );
 
         // No hours
-        test_is_none(
+        test_is_rejected(
             r#"{ "data": { "name": "alarm_update", "body": { "minute": 6 } }, "unique": "random_string"}"#,
         );
 
         // invalid hours - number as string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_update", "body": { "hour": "6", "minute": 4 } }, "unique": "random_string" }"#,
         );
 
         // invalid hours - string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_update", "body": { "hour": "string", "minute": 4 } }, "unique": "random_string" }"#,
         );
 
@@ -238,17 +764,17 @@ mod tests {
         );
 
         // invalid minute - number as string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_update", "body": { "hour" :6, "minute": "4" } }, "unique": "random_string" }"#,
         );
 
         // invalid minute - string
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_update", "body": { "hour": 6, "minute": "string" } }, "unique": "random_string" }"#,
         );
 
         // invalid minute- > 59
-        test_is_none(
+        test_is_rejected(
             r#" { "data": { "name": "alarm_update", "body": { "hour": 6, "minute": 60 } }, "unique": "random_string"}"#,
         );
 
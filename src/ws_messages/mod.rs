@@ -1,6 +1,10 @@
+mod error;
 mod incoming;
 mod outgoing;
+mod schema;
 mod serializer;
 
+pub use error::*;
 pub use incoming::*;
 pub use outgoing::*;
+pub use schema::*;
@@ -1,12 +1,52 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::{db::ModelAlarm, sysinfo::SysInfo};
+use sqlx::SqlitePool;
+
+use crate::{
+    S,
+    app_env::AppEnv,
+    app_error::AppError,
+    credentials::CredentialStatus,
+    db::{ModelAlarm, ModelRequest},
+    notify::Channel,
+    request::PushRequest,
+    sysinfo::SysInfo,
+    ws::ConnectionStats,
+    ws_messages::{ErrorResponse, ParsedMessage},
+};
+
+/// Version of the ws message format, increased on any breaking change to `ParsedMessage` or `Response`
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Oldest client protocol version that is still supported
+pub const PROTOCOL_VERSION_MIN: u16 = 1;
+
+/// Sent on connect, and in response to a client hello, so that a client knows what it can send
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Hello {
+    pub protocol: u16,
+    pub version: String,
+    pub commands: Vec<String>,
+}
+
+impl Hello {
+    /// The protocol is the highest version supported by both sides
+    pub fn new(protocol: u16) -> Self {
+        Self {
+            protocol: protocol.min(PROTOCOL_VERSION),
+            version: S!(env!("CARGO_PKG_VERSION")),
+            commands: ParsedMessage::commands().to_vec(),
+        }
+    }
+}
 
 /// Basic pi info
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct PiStatus {
     pub alarm: Option<ModelAlarm>,
+    pub connection: ConnectionStats,
+    pub credentials: Option<CredentialStatus>,
     pub time_zone: String,
     pub uptime_app: u64,
     pub uptime_ws: u64,
@@ -15,9 +55,17 @@ pub struct PiStatus {
 }
 /// Combined pi into and current set alarms
 impl PiStatus {
-    pub fn new(sysinfo: SysInfo, alarm: Option<ModelAlarm>, uptime_ws: u64) -> Self {
+    pub fn new(
+        sysinfo: SysInfo,
+        alarm: Option<ModelAlarm>,
+        uptime_ws: u64,
+        credentials: Option<CredentialStatus>,
+        connection: ConnectionStats,
+    ) -> Self {
         Self {
             alarm,
+            connection,
+            credentials,
             time_zone: sysinfo.time_zone,
             uptime_app: sysinfo.uptime_app,
             uptime: sysinfo.uptime,
@@ -26,28 +74,115 @@ impl PiStatus {
         }
     }
 }
+/// Requests remaining, for a single channel, recipient, and type of request, before the rate limit is reached
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Budget {
+    pub channel: Channel,
+    pub recipient: String,
+    pub is_alarm: bool,
+    pub hour_limit: i64,
+    pub hour_remaining: i64,
+    pub day_limit: i64,
+    pub day_remaining: i64,
+}
+
+impl Budget {
+    /// Budget for every channel in the notify chain, Pushover is limited per recipient, the other channels only have a single destination
+    pub async fn get(app_envs: &AppEnv, sqlite: &SqlitePool) -> Result<Vec<Self>, AppError> {
+        let mut output = vec![];
+        for channel in &app_envs.notify_chain {
+            let recipients = match channel {
+                Channel::Pushover => app_envs
+                    .recipients
+                    .iter()
+                    .map(|i| i.name.as_str())
+                    .collect(),
+                Channel::Telegram | Channel::Matrix => vec![""],
+            };
+            for recipient in recipients {
+                for push_request in [
+                    PushRequest::Alarm {
+                        alarm_id: 0,
+                        push_index: 0,
+                    },
+                    PushRequest::TestRequest,
+                ] {
+                    let limit = app_envs.limit(*channel, &push_request);
+                    let count =
+                        ModelRequest::count_recent(sqlite, &push_request, *channel, recipient)
+                            .await?;
+                    output.push(Self {
+                        channel: *channel,
+                        recipient: recipient.to_owned(),
+                        is_alarm: matches!(push_request, PushRequest::Alarm { .. }),
+                        hour_limit: limit.hour,
+                        hour_remaining: (limit.hour - count.hour).max(0),
+                        day_limit: limit.day,
+                        day_remaining: (limit.day - count.day).max(0),
+                    });
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
 /// Responses, either sent as is, or nested in StructuredResponse below
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case", tag = "name", content = "data")]
 pub enum Response {
-    Status(PiStatus),
-    LedStatus { status: bool },
-    Error(String),
+    Hello(Hello),
+    Status(Box<PiStatus>),
+    LedStatus {
+        status: bool,
+    },
+    Budget(Vec<Budget>),
+    RequestLog(Vec<ModelRequest>),
+    /// Every channel in the notify chain failed to deliver an alarm push
+    NotifyFailed {
+        push_index: u8,
+        errors: String,
+    },
+    /// The alarm loop has been (re)generated for this alarm
+    AlarmScheduled(ModelAlarm),
+    /// The alarm time has been reached, and pushes are about to start
+    AlarmStarted {
+        push_total: u8,
+    },
+    /// A single push of a ringing alarm, success is false if every channel failed
+    AlarmPush {
+        push_index: u8,
+        push_total: u8,
+        success: bool,
+    },
+    /// A ringing alarm was dismissed
+    AlarmDismissed {
+        push_index: u8,
+    },
+    /// A ringing alarm stopped without being dismissed, either every push was sent, or every channel is disabled
+    AlarmRanOut {
+        push_index: u8,
+    },
 }
 
 /// These get sent to the websocket server when in structured_data mode,
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct StructuredResponse {
     data: Option<Response>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<Response>,
+    error: Option<ErrorResponse>,
     #[serde(skip_serializing_if = "Option::is_none")]
     cache: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    success: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     unique: Option<String>,
 }
 
+/// The outcome of a single client command, an optional payload, or the reason it failed
+pub type CommandResult = Result<Option<Response>, ErrorResponse>;
+
 impl StructuredResponse {
     /// Convert a ResponseMessage into a Tokio message of StructureResponse
     pub fn data(data: Response, cache: Option<bool>, unique: Option<String>) -> Message {
@@ -55,19 +190,114 @@ impl StructuredResponse {
             data: Some(data),
             error: None,
             cache,
+            success: None,
             unique,
         };
         Message::Text(serde_json::to_string(&x).unwrap_or_default().into())
     }
 
-    /// Convert a ErrorResponse into a Tokio message of StructureResponse
-    pub fn _error(data: Response) -> Message {
+    /// Convert the result of a command into a Tokio message of StructureResponse, every command gets exactly one of these, with the unique of the request
+    pub fn ack(result: CommandResult, unique: String) -> Message {
+        let (data, error) = match result {
+            Ok(data) => (data, None),
+            Err(error) => (None, Some(error)),
+        };
         let x = Self {
-            error: Some(data),
-            data: None,
-            cache: None,
-            unique: None,
+            cache: matches!(data, Some(Response::Status(_))).then_some(true),
+            success: Some(error.is_none()),
+            data,
+            error,
+            unique: Some(unique),
         };
         Message::Text(serde_json::to_string(&x).unwrap_or_default().into())
     }
 }
+
+/// message_outgoing
+///
+/// cargo watch -q -c -w src/ -x 'test message_outgoing -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        S,
+        app_env::{ChannelLimits, DEFAULT_RECIPIENT, RateLimit},
+        tests::{test_cleanup, test_setup},
+    };
+
+    #[tokio::test]
+    async fn message_outgoing_budget() {
+        let (mut app_envs, sqlite, uuid) = test_setup().await;
+        app_envs.notify_chain = vec![Channel::Pushover, Channel::Matrix];
+        app_envs.limits.insert(
+            Channel::Pushover,
+            ChannelLimits {
+                alarm: RateLimit { hour: 2, day: 5 },
+                test: RateLimit { hour: 3, day: 4 },
+            },
+        );
+        for _ in 0..3 {
+            ModelRequest::insert(
+                &sqlite,
+                &PushRequest::test_alarm(1),
+                Channel::Pushover,
+                DEFAULT_RECIPIENT,
+                "",
+            )
+            .await
+            .unwrap();
+        }
+        ModelRequest::insert(&sqlite, &PushRequest::TestRequest, Channel::Matrix, "", "")
+            .await
+            .unwrap();
+
+        let result = Budget::get(&app_envs, &sqlite).await.unwrap();
+
+        assert_eq!(result.len(), 4);
+        assert_eq!(
+            result[0],
+            Budget {
+                channel: Channel::Pushover,
+                recipient: S!(DEFAULT_RECIPIENT),
+                is_alarm: true,
+                hour_limit: 2,
+                hour_remaining: 0,
+                day_limit: 5,
+                day_remaining: 2,
+            }
+        );
+        assert_eq!(result[1].hour_remaining, 3);
+        assert_eq!(result[1].day_remaining, 4);
+        assert!(!result[1].is_alarm);
+        assert_eq!(result[2].channel, Channel::Matrix);
+        assert_eq!(result[2].recipient, "");
+        assert_eq!(result[2].hour_remaining, 60);
+        assert_eq!(result[3].hour_remaining, 9);
+        assert_eq!(result[3].day_remaining, 49);
+
+        test_cleanup(uuid, Some(sqlite)).await;
+    }
+
+    #[test]
+    // Every ack has the unique of the request, and a success flag
+    fn message_outgoing_ack() {
+        let result = StructuredResponse::ack(Ok(None), S!("unique"));
+        assert_eq!(
+            result.to_text().unwrap(),
+            r#"{"data":null,"success":true,"unique":"unique"}"#
+        );
+
+        let result = StructuredResponse::ack(Ok(Some(Response::Budget(vec![]))), S!("unique"));
+        assert_eq!(
+            result.to_text().unwrap(),
+            r#"{"data":{"name":"budget","data":[]},"success":true,"unique":"unique"}"#
+        );
+
+        let result = StructuredResponse::ack(Err(ErrorResponse::alarm_locked(7, 30)), S!("unique"));
+        assert_eq!(
+            result.to_text().unwrap(),
+            r#"{"data":null,"error":{"code":"alarm_locked","message":"Current time too close to alarm to edit","details":{"kind":"unlock_at","hour":7,"minute":31}},"success":false,"unique":"unique"}"#
+        );
+    }
+}
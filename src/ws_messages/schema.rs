@@ -0,0 +1,74 @@
+use schemars::{Schema, generate::SchemaSettings};
+use serde::Serialize;
+
+use super::{PROTOCOL_VERSION, StructuredResponse, incoming::StructuredMessage};
+
+/// Both directions of the ws protocol, incoming messages are described as they're deserialized, outgoing as they're serialized
+#[derive(Serialize)]
+struct ProtocolSchema {
+    protocol: u16,
+    incoming: Schema,
+    outgoing: Schema,
+}
+
+/// JSON Schema of every ws message, printed by the `schema` subcommand
+pub fn protocol_schema() -> String {
+    let schema = ProtocolSchema {
+        protocol: PROTOCOL_VERSION,
+        incoming: SchemaSettings::draft2020_12()
+            .for_deserialize()
+            .into_generator()
+            .into_root_schema_for::<StructuredMessage>(),
+        outgoing: SchemaSettings::draft2020_12()
+            .for_serialize()
+            .into_generator()
+            .into_root_schema_for::<StructuredResponse>(),
+    };
+    let mut output = serde_json::to_string_pretty(&schema).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+/// message_schema
+///
+/// cargo watch -q -c -w src/ -x 'test message_schema -- --nocapture'
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// Location of the checked in schema, relative to the crate root
+    const SCHEMA_PATH: &str = "schema/ws_protocol.json";
+
+    #[test]
+    // Any change to a ws message must be reflected in the checked in schema
+    fn message_schema_drift() {
+        let path = format!("{}/{SCHEMA_PATH}", env!("CARGO_MANIFEST_DIR"));
+        let checked_in = std::fs::read_to_string(path).unwrap();
+        assert!(
+            checked_in == protocol_schema(),
+            "{SCHEMA_PATH} is out of date, regenerate with `cargo run -- schema > {SCHEMA_PATH}`"
+        );
+    }
+
+    #[test]
+    // IncomingSerializer constraints are included
+    fn message_schema_constraints() {
+        let schema = serde_json::from_str::<serde_json::Value>(&protocol_schema()).unwrap();
+        let defs = &schema["incoming"]["$defs"];
+        assert_eq!(defs["HourMinuteMsg"]["properties"]["hour"]["maximum"], 23);
+        assert_eq!(defs["HourMinuteMsg"]["properties"]["minute"]["maximum"], 59);
+        assert_eq!(
+            defs["TestRequest"]["properties"]["message"]["maxLength"],
+            100
+        );
+        assert_eq!(
+            defs["RequestLogFilter"]["properties"]["limit"]["maximum"],
+            500
+        );
+        assert_eq!(
+            defs["PushOptions"]["dependentRequired"]["url_title"][0],
+            "url"
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Deserializer, de};
-use std::ops::RangeInclusive;
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use super::PushOptions;
+use crate::app_env::Recipient;
+
 pub struct IncomingSerializer;
 
 impl IncomingSerializer {
@@ -48,6 +52,197 @@ impl IncomingSerializer {
         }
     }
 
+    /// Max 10 recipient names, each 1-32 chars of `[a-zA-Z0-9_-]`, an invalid name is reported by its index, never echoed back
+    pub fn recipients<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<Vec<String>>::deserialize(deserializer)?;
+        if let Some(names) = parsed.as_ref() {
+            if names.len() > 10 {
+                return Err(de::Error::custom("too many recipients"));
+            }
+            if let Some(index) = names.iter().position(|name| !Recipient::valid_name(name)) {
+                return Err(de::Error::custom(format!(
+                    "invalid recipient at index {index}"
+                )));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// A single, optional, recipient name
+    pub fn recipient<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<String>::deserialize(deserializer)?;
+        if let Some(name) = parsed.as_ref()
+            && !Recipient::valid_name(name)
+        {
+            return Err(de::Error::custom("invalid recipient"));
+        }
+        Ok(parsed)
+    }
+
+    /// Pushover tokens and user keys are 30 chars of [A-Za-z0-9], the key itself is never included in the error
+    fn valid_pushover_key(key: &str) -> bool {
+        key.len() == 30 && key.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+
+    /// An optional Pushover application token
+    pub fn pushover_key<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<String>::deserialize(deserializer)?;
+        if parsed
+            .as_ref()
+            .is_some_and(|i| !Self::valid_pushover_key(i))
+        {
+            return Err(de::Error::custom("invalid pushover key"));
+        }
+        Ok(parsed)
+    }
+
+    /// Max 10 recipient names, each mapped to a Pushover user key, an invalid name is reported by its index, never echoed back
+    pub fn pushover_users<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = BTreeMap::<String, String>::deserialize(deserializer)?;
+        if parsed.len() > 10 {
+            return Err(de::Error::custom("too many recipients"));
+        }
+        for (index, (name, user)) in parsed.iter().enumerate() {
+            if !Recipient::valid_name(name) {
+                return Err(de::Error::custom(format!(
+                    "invalid recipient at index {index}"
+                )));
+            }
+            if !Self::valid_pushover_key(user) {
+                return Err(de::Error::custom(format!("invalid pushover key: {name}")));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Number of request log rows to return, 1-500
+    pub fn log_limit<'de, D>(deserializer: D) -> Result<u16, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = u16::deserialize(deserializer)?;
+        if !(1..=500).contains(&parsed) {
+            return Err(de::Error::custom("limit must be between 1 and 500"));
+        }
+        Ok(parsed)
+    }
+
+    /// Check an optional string is no longer than a given number of chars
+    fn max_chars<'de, D>(
+        deserializer: D,
+        max: usize,
+        name: &str,
+    ) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<String>::deserialize(deserializer)?;
+        if parsed.as_ref().is_some_and(|i| i.chars().count() > max) {
+            return Err(de::Error::custom(format!("{name} too long")));
+        }
+        Ok(parsed)
+    }
+
+    /// Pushover title can be 250 chars max
+    pub fn title<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::max_chars(deserializer, 250, "title")
+    }
+
+    /// Pushover url title can be 100 chars max
+    pub fn url_title<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::max_chars(deserializer, 100, "url_title")
+    }
+
+    /// Pushover url can be 512 chars max, and must be a valid http(s) url
+    pub fn url<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Self::max_chars(deserializer, 512, "url")?;
+        if let Some(url) = parsed.as_ref()
+            && !url::Url::parse(url).is_ok_and(|i| i.scheme() == "https" || i.scheme() == "http")
+        {
+            return Err(de::Error::custom("invalid url"));
+        }
+        Ok(parsed)
+    }
+
+    /// Sound can be either a built-in or custom Pushover sound, so just check that it's 1-20 chars of `[a-zA-Z0-9_-]`
+    pub fn sound<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<String>::deserialize(deserializer)?;
+        if let Some(sound) = parsed.as_ref()
+            && (!(1..=20).contains(&sound.len())
+                || !sound
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        {
+            return Err(de::Error::custom("invalid sound"));
+        }
+        Ok(parsed)
+    }
+
+    /// Time to live, in seconds, must be greater than 0
+    pub fn ttl<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<u32>::deserialize(deserializer)?;
+        if parsed == Some(0) {
+            return Err(de::Error::custom("ttl must be greater than 0"));
+        }
+        Ok(parsed)
+    }
+
+    /// Unix timestamp, must be greater than 0
+    pub fn timestamp<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<i64>::deserialize(deserializer)?;
+        if parsed.is_some_and(|i| i <= 0) {
+            return Err(de::Error::custom("timestamp must be greater than 0"));
+        }
+        Ok(parsed)
+    }
+
+    /// Validate the options as a whole, html and monospace are mutually exclusive, and a url_title needs a url
+    pub fn push_options<'de, D>(deserializer: D) -> Result<PushOptions, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed = Option::<PushOptions>::deserialize(deserializer)?.unwrap_or_default();
+        if parsed.html && parsed.monospace {
+            return Err(de::Error::custom(
+                "html and monospace are mutually exclusive",
+            ));
+        }
+        if parsed.url_title.is_some() && parsed.url.is_none() {
+            return Err(de::Error::custom("url_title requires url"));
+        }
+        Ok(parsed)
+    }
+
     /// Use timezones crate to make sure is valid timezone
     pub fn timezone<'de, D>(deserializer: D) -> Result<String, D::Error>
     where
@@ -70,7 +265,7 @@ mod tests {
     use serde::de::IntoDeserializer;
     use serde::de::value::{Error as ValueError, StringDeserializer, U8Deserializer};
 
-    use crate::S;
+    use crate::{C, S};
 
     use super::*;
 
@@ -130,6 +325,183 @@ mod tests {
         assert_eq!(result.unwrap(), "America/New_York");
     }
 
+    #[test]
+    fn incoming_serializer_recipients_ok() {
+        let mut deserializer = serde_json::Deserializer::from_str(r#"["jack", "sam_2"]"#);
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert_eq!(result.unwrap(), Some(vec![S!("jack"), S!("sam_2")]));
+
+        let mut deserializer = serde_json::Deserializer::from_str("null");
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn incoming_serializer_recipients_err() {
+        let mut deserializer = serde_json::Deserializer::from_str(r#"["ja ck"]"#);
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "invalid recipient at index 0"
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#"["jack", "ja ck"]"#);
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "invalid recipient at index 1"
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#"[""]"#);
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert!(result.is_err());
+
+        let too_many = serde_json::to_string(&vec!["a"; 11]).unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(&too_many);
+        let result = IncomingSerializer::recipients(&mut deserializer);
+        assert_eq!(result.unwrap_err().to_string(), "too many recipients");
+    }
+
+    #[test]
+    fn incoming_serializer_push_options_fields() {
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::push_options(&mut deserializer).map_err(|e| e.to_string())
+        };
+
+        let result = test(&format!(r#"{{"title":"{}"}}"#, "a".repeat(250)));
+        assert!(result.is_ok());
+        let result = test(&format!(r#"{{"title":"{}"}}"#, "a".repeat(251)));
+        assert!(result.unwrap_err().starts_with("title too long"));
+
+        let result = test(&format!(
+            r#"{{"url":"https://www.example.com","url_title":"{}"}}"#,
+            "a".repeat(101)
+        ));
+        assert!(result.unwrap_err().starts_with("url_title too long"));
+
+        let result = test(&format!(
+            r#"{{"url":"https://www.example.com/{}"}}"#,
+            "a".repeat(512)
+        ));
+        assert!(result.unwrap_err().starts_with("url too long"));
+        let result = test(r#"{"url":"ftp://www.example.com"}"#);
+        assert!(result.unwrap_err().starts_with("invalid url"));
+        let result = test(r#"{"url":"http://www.example.com"}"#);
+        assert_eq!(result.unwrap().url, Some(S!("http://www.example.com")));
+
+        let result = test(r#"{"sound":"spacealarm"}"#);
+        assert_eq!(result.unwrap().sound, Some(S!("spacealarm")));
+        let result = test(r#"{"sound":"space alarm"}"#);
+        assert!(result.unwrap_err().starts_with("invalid sound"));
+        let result = test(r#"{"sound":""}"#);
+        assert!(result.unwrap_err().starts_with("invalid sound"));
+
+        let result = test(r#"{"ttl":0}"#);
+        assert!(
+            result
+                .unwrap_err()
+                .starts_with("ttl must be greater than 0")
+        );
+        let result = test(r#"{"ttl":-1}"#);
+        assert!(result.is_err());
+
+        let result = test(r#"{"timestamp":0}"#);
+        assert!(
+            result
+                .unwrap_err()
+                .starts_with("timestamp must be greater than 0")
+        );
+    }
+
+    #[test]
+    fn incoming_serializer_push_options() {
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::push_options(&mut deserializer).map_err(|e| e.to_string())
+        };
+
+        assert_eq!(test("null").unwrap(), PushOptions::default());
+        assert_eq!(test("{}").unwrap(), PushOptions::default());
+
+        let result = test(r#"{"html":true,"monospace":true}"#);
+        assert_eq!(
+            result.unwrap_err(),
+            "html and monospace are mutually exclusive"
+        );
+
+        let result = test(r#"{"url_title":"title"}"#);
+        assert_eq!(result.unwrap_err(), "url_title requires url");
+    }
+
+    #[test]
+    fn incoming_serializer_request_log() {
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::log_limit(&mut deserializer).map_err(|e| e.to_string())
+        };
+        assert_eq!(test("1").unwrap(), 1);
+        assert_eq!(test("500").unwrap(), 500);
+        for limit in ["0", "501", "-1"] {
+            assert!(test(limit).is_err());
+        }
+
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::recipient(&mut deserializer).map_err(|e| e.to_string())
+        };
+        assert_eq!(test(r#""jack""#).unwrap(), Some(S!("jack")));
+        assert_eq!(test("null").unwrap(), None);
+        assert_eq!(test(r#""""#).unwrap_err(), "invalid recipient");
+        assert!(test(r#""ja ck""#).is_err());
+    }
+
+    #[test]
+    fn incoming_serializer_credentials() {
+        let key = "a".repeat(29) + "1";
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::pushover_key(&mut deserializer).map_err(|e| e.to_string())
+        };
+        assert_eq!(test(&format!(r#""{key}""#)).unwrap(), Some(C!(key)));
+        assert_eq!(test("null").unwrap(), None);
+        for invalid in ["short", &"a".repeat(31), &"a-".repeat(15)] {
+            let result = test(&format!(r#""{invalid}""#)).unwrap_err();
+            assert_eq!(result, "invalid pushover key");
+        }
+
+        let test = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            IncomingSerializer::pushover_users(&mut deserializer).map_err(|e| e.to_string())
+        };
+        let result = test(&format!(r#"{{"jack":"{key}"}}"#)).unwrap();
+        assert_eq!(result.get("jack"), Some(&key));
+        assert!(test("{}").unwrap().is_empty());
+        assert_eq!(
+            test(&format!(r#"{{"ja ck":"{key}"}}"#)).unwrap_err(),
+            "invalid recipient at index 0"
+        );
+        // The name isn't included in the error, whatever its length
+        let long = "a ".repeat(5000);
+        assert_eq!(
+            test(&format!(r#"{{"{long}":"{key}","jack":"{key}"}}"#)).unwrap_err(),
+            "invalid recipient at index 0"
+        );
+        // The key isn't included in the error
+        assert_eq!(
+            test(r#"{"jack":"secret"}"#).unwrap_err(),
+            "invalid pushover key: jack"
+        );
+        let many = (0..11)
+            .map(|i| format!(r#""name{i}":"{key}""#))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(
+            test(&format!("{{{many}}}")).unwrap_err(),
+            "too many recipients"
+        );
+    }
+
     #[test]
     fn incoming_serializer_message_err() {
         let deserializer: StringDeserializer<ValueError> = "a".repeat(101).into_deserializer();